@@ -17,5 +17,13 @@ if ! cp './target/release/batteryctl' '/usr/local/bin/'; then
     exit 3
 fi
 
+# The privileged helper, started by D-Bus when batteryctl can't write a charge setting itself.
+if ! install -Dm644 './dist/io.github.DucktectiveCZ.Batteryctl1.conf' '/usr/share/dbus-1/system.d/io.github.DucktectiveCZ.Batteryctl1.conf' \
+    || ! install -Dm644 './dist/io.github.ducktectivecz.batteryctl.policy' '/usr/share/polkit-1/actions/io.github.ducktectivecz.batteryctl.policy' \
+    || ! sed 's|/usr/bin/|/usr/local/bin/|' './dist/io.github.DucktectiveCZ.Batteryctl1.service' > '/usr/share/dbus-1/system-services/io.github.DucktectiveCZ.Batteryctl1.service'; then
+    echo "Installing the helper failed, exiting..."
+    exit 4
+fi
+
 echo "Batteryctl was successfully installed on your system! For the usage, read the docs or run \`batteryctl --help\`."
 echo "Thanks for using batteryctl! :D"
@@ -17,61 +17,181 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
+use std::path::PathBuf;
+
 use clap::{Parser, ValueEnum};
 
+use crate::bar::BarFormat;
+use crate::battery;
+use crate::charge::ChargeBehaviour;
+use crate::output::OutputFormat;
+use crate::watch;
+
 #[derive(ValueEnum, Clone, Debug)]
 pub enum Operation {
     GetProperty,
+    SetProperty,
     ListDevices,
+    Status,
+    TimeLeft,
+    Health,
+    History,
+    Sessions,
+    ChargeLimit,
+    Watch,
+    Bar,
     Daemon,
+    Helper,
     ConfigGet,
     ConfigSet,
     Version,
     V,
 }
 
+/// How `history` prints the samples.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistoryFormat {
+    Chart,
+    Sparkline,
+    Csv,
+    Jsonl,
+}
+
 #[derive(ValueEnum, Clone, Debug)]
 pub enum ConfigKey {
     Batteries,
     ReadDelay,
-    GoodCapacity,
-    OkayCapacity,
-    BadCapacity,
-    CriticalCapacity,
-    GoodCapacityHandler,
-    OkayCapacityHandler,
-    BadCapacityHandler,
-    CriticalCapacityHandler,
+    Hysteresis,
+    Thresholds,
+    AcConnectedHandler,
+    AcDisconnectedHandler,
+    ChargingStartedHandler,
+    DischargingStartedHandler,
+    FullHandler,
 }
 
 #[derive(Parser)]
 pub struct Args {
     pub operation: Operation,
-    #[arg(required_if_eq("operation", "config-get"), required_if_eq("operation", "config-set"))]
-    pub key: Option<ConfigKey>,
+    /// The config key, or the property name for `get-property` and `set-property`.
+    #[arg(
+        required_if_eq("operation", "config-get"),
+        required_if_eq("operation", "config-set"),
+    )]
+    pub key: Option<String>,
+    /// The value for `config-set` and `set-property`.
     #[arg(required_if_eq("operation", "config-set"))]
     pub value: Option<String>,
     #[arg(long, short, default_value("BAT0"))]
     pub device: Option<String>,
-    #[arg(required_if_eq("operation", "get-property"))]
-    pub property: Option<String>,
-    #[arg(long, short)]
-    pub read_delay: Option<u64>,
-    #[arg(long, short)]
-    pub good_capacity: Option<u8>,
-    #[arg(long, short)]
-    pub okay_capacity: Option<u8>,
+    /// Print every property of the device with `get-property`, or use every system battery with `health` and every battery with `history` and `sessions`.
     #[arg(long, short)]
-    pub bad_capacity: Option<u8>,
+    pub all: bool,
+    /// Only list devices of this type with `list-devices`.
+    #[arg(long = "type")]
+    pub device_type: Option<battery::DeviceType>,
+    /// Only list devices of this scope with `list-devices`.
+    #[arg(long)]
+    pub scope: Option<battery::Scope>,
+    /// Print a table with the state, model, capacity and status of each device.
     #[arg(long, short)]
-    pub critical_capacity: Option<u8>,
+    pub wide: bool,
+    /// Show how fast the battery loses capacity with `health`.
+    #[arg(long)]
+    pub trend: bool,
+    /// Only use history samples from this time on: a Unix timestamp, a date like
+    /// 2024-05-01 or 2024-05-01T14:30, or a duration ago like 12h or 7d.
     #[arg(long)]
-    pub good_capacity_handler: Option<String>,
+    pub since: Option<String>,
+    /// Only use history samples up to this time, in the same forms as `--since`.
     #[arg(long)]
-    pub okay_capacity_handler: Option<String>,
+    pub until: Option<String>,
+    #[arg(long, value_enum, default_value_t = HistoryFormat::Chart)]
+    pub format: HistoryFormat,
+    /// Print the result, or the error, as JSON or TOML instead of text.
+    #[arg(long, short, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+    /// Set the percentage below which the battery starts charging again with `charge-limit`.
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub start: Option<u8>,
+    /// Set the percentage at which the battery stops charging with `charge-limit`.
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub end: Option<u8>,
+    /// Set what the battery does while the charger is connected with `charge-limit`.
+    #[arg(long, value_enum)]
+    pub behaviour: Option<ChargeBehaviour>,
+    /// Show what `set-property` would write without writing it.
     #[arg(long)]
-    pub bad_capacity_handler: Option<String>,
+    pub dry_run: bool,
+    /// The properties `watch` prints when they change, separated by commas.
+    #[arg(long, value_delimiter = ',', default_values_t = watch::DEFAULT_PROPERTIES.map(String::from))]
+    pub properties: Vec<String>,
+    /// How often `watch` re-reads the device in milliseconds, in case its driver doesn't send uevents.
+    #[arg(long, default_value_t = watch::DEFAULT_INTERVAL_MS, value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: u64,
+    /// The status bar `bar` prints for.
+    #[arg(long, value_enum, default_value_t = BarFormat::Waybar)]
+    pub bar_format: BarFormat,
+    /// Keep printing `bar` whenever the battery changes, for bars that read a stream.
     #[arg(long)]
-    pub critical_capacity_handler: Option<String>,
+    pub continuous: bool,
+    #[arg(long, env = battery::SYSFS_ROOT_ENV, default_value(battery::BATTERIES_DIRECTORY))]
+    pub sysfs_root: PathBuf,
+    #[arg(long, short)]
+    pub read_delay: Option<u64>,
+}
+
+
+impl ConfigKey {
+    /// The key's name in the config file.
+    pub fn field(&self) -> &'static str {
+        match self {
+            ConfigKey::Batteries => "batteries",
+            ConfigKey::ReadDelay => "read_delay_ms",
+            ConfigKey::Hysteresis => "hysteresis",
+            ConfigKey::Thresholds => "threshold",
+            ConfigKey::AcConnectedHandler => "ac_connected_handler",
+            ConfigKey::AcDisconnectedHandler => "ac_disconnected_handler",
+            ConfigKey::ChargingStartedHandler => "charging_started_handler",
+            ConfigKey::DischargingStartedHandler => "discharging_started_handler",
+            ConfigKey::FullHandler => "full_handler",
+        }
+    }
 }
 
+/// Finds the `--output` format in raw arguments, for reporting errors in
+/// arguments that don't parse.
+pub fn requested_output(args: impl Iterator<Item = String>) -> Option<OutputFormat> {
+    let mut args = args.skip(1);
+    let mut format = None;
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--" => break,
+            "--output" | "-o" => args.next(),
+            _ => arg
+                .strip_prefix("--output=")
+                .or_else(|| arg.strip_prefix("-o").filter(|_| !arg.starts_with("--")))
+                .map(|v| v.trim_start_matches('=').to_string()),
+        };
+        if let Some(parsed) = value.and_then(|v| OutputFormat::from_str(&v, true).ok()) {
+            format = Some(parsed);
+        }
+    }
+
+    format
+}
+
+impl Args {
+    pub fn config_key(&self) -> Result<ConfigKey, String> {
+        let key = self.key.as_ref().unwrap();
+
+        ConfigKey::from_str(key, true).map_err(|_| {
+            let possible: Vec<String> = ConfigKey::value_variants()
+                .iter()
+                .filter_map(|k| k.to_possible_value())
+                .map(|v| v.get_name().to_string())
+                .collect();
+            format!("Invalid config key '{}'. Possible keys: {}", key, possible.join(", "))
+        })
+    }
+}
@@ -0,0 +1,203 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! The values the well-known writable power_supply attributes accept, from
+//! the kernel's sysfs-class-power ABI.
+
+use crate::battery::Properties;
+
+/// What an attribute accepts.
+enum Accepts {
+    Percentage,
+    /// A non-negative integer, usually in µA, µV or µW.
+    Unsigned,
+    /// An integer up to the value of another attribute.
+    UpTo(&'static str),
+    /// One of a fixed set of words.
+    OneOf(&'static [&'static str]),
+    /// One of the words the attribute lists, like `[auto] inhibit-charge`, or
+    /// of the fallback words when it shows only the selected one.
+    Listed(&'static [&'static str]),
+}
+
+const KNOWN: &[(&str, Accepts)] = &[
+    ("charge_control_start_threshold", Accepts::Percentage),
+    ("charge_control_end_threshold", Accepts::Percentage),
+    // The older names some drivers still use.
+    ("charge_start_threshold", Accepts::Percentage),
+    ("charge_stop_threshold", Accepts::Percentage),
+    ("capacity_alert_min", Accepts::Percentage),
+    ("capacity_alert_max", Accepts::Percentage),
+    ("charge_control_limit", Accepts::UpTo("charge_control_limit_max")),
+    ("constant_charge_current", Accepts::UpTo("constant_charge_current_max")),
+    ("constant_charge_voltage", Accepts::UpTo("constant_charge_voltage_max")),
+    ("input_current_limit", Accepts::Unsigned),
+    ("input_voltage_limit", Accepts::Unsigned),
+    ("input_power_limit", Accepts::Unsigned),
+    ("precharge_current", Accepts::Unsigned),
+    ("charge_term_current", Accepts::Unsigned),
+    ("charge_behaviour", Accepts::Listed(&["auto", "inhibit-charge", "force-discharge"])),
+    ("charge_type", Accepts::OneOf(&["Trickle", "Fast", "Standard", "Adaptive", "Custom", "Long Life", "Bypass"])),
+    ("charge_types", Accepts::Listed(&[])),
+    ("usb_type", Accepts::Listed(&[])),
+];
+
+fn accepts(name: &str) -> Option<&'static Accepts> {
+    KNOWN.iter().find(|(known, _)| *known == name).map(|(_, accepts)| accepts)
+}
+
+/// The words a listing attribute offers, without the brackets around the selected one.
+fn listed(current: Option<&str>, fallback: &'static [&'static str]) -> Vec<String> {
+    match current {
+        Some(val) if val.contains('[') => val
+            .split_whitespace()
+            .map(|word| word.trim_matches(['[', ']']).to_string())
+            .collect(),
+        _ => fallback.iter().map(|word| word.to_string()).collect(),
+    }
+}
+
+/// Describes what a well-known attribute accepts, e.g. `0-100` or `auto, inhibit-charge`,
+/// or `None` for attributes batteryctl doesn't know.
+pub fn describe(props: &Properties, name: &str) -> Option<String> {
+    let description = match accepts(name)? {
+        Accepts::Percentage => "0-100".to_string(),
+        Accepts::Unsigned => "0 or more".to_string(),
+        Accepts::UpTo(max) => match read_max(props, max) {
+            Some(val) => format!("0-{}", val),
+            None => "0 or more".to_string(),
+        },
+        Accepts::OneOf(words) => words.join(", "),
+        Accepts::Listed(fallback) => {
+            let current = props.read_attribute(name).ok().flatten();
+            listed(current.as_deref(), fallback).join(", ")
+        }
+    };
+
+    Some(description)
+}
+
+fn read_max(props: &Properties, max: &str) -> Option<u64> {
+    props.read_attribute(max).ok().flatten()?.trim().parse().ok()
+}
+
+/// Checks a value against what a well-known attribute accepts. Returns
+/// `Ok(false)` for attributes batteryctl doesn't know, which it can't check.
+pub fn validate(props: &Properties, name: &str, value: &str) -> Result<bool, String> {
+    let accepts = match accepts(name) {
+        Some(val) => val,
+        None => return Ok(false),
+    };
+    let invalid = || format!("Invalid value '{}' for '{}', expected {}", value, name, describe(props, name).unwrap_or_default());
+
+    let valid = match accepts {
+        Accepts::Percentage => value.parse::<u8>().is_ok_and(|v| v <= 100),
+        Accepts::Unsigned => value.parse::<u64>().is_ok(),
+        Accepts::UpTo(max) => value
+            .parse::<u64>()
+            .is_ok_and(|v| read_max(props, max).is_none_or(|max| v <= max)),
+        Accepts::OneOf(words) => words.contains(&value),
+        Accepts::Listed(fallback) => {
+            let current = props.read_attribute(name).map_err(|e| e.to_string())?;
+            let words = listed(current.as_deref(), fallback);
+            // Without a listing or fallback any word would pass, so nothing is.
+            if words.is_empty() {
+                return Err(format!("'{}' of {} doesn't list the values it accepts", name, props.name));
+            }
+            words.iter().any(|w| w == value)
+        }
+    };
+
+    match valid {
+        true => Ok(true),
+        false => Err(invalid()),
+    }
+}
+
+/// Explains how to get write access to an attribute without running as root.
+pub fn permission_hint(props: &Properties, name: &str) -> String {
+    format!(
+        concat!(
+            "Permission denied writing '{name}' of {device}. Run batteryctl as root, install the ",
+            "batteryctl helper for the charge settings, or let the 'power' group write it with a ",
+            "udev rule in /etc/udev/rules.d/99-batteryctl.rules:\n",
+            "  ACTION==\"add\", SUBSYSTEM==\"power_supply\", KERNEL==\"{device}\", ",
+            "RUN+=\"/bin/chgrp power /sys%p/{name}\", RUN+=\"/bin/chmod g+w /sys%p/{name}\"",
+        ),
+        name = name,
+        device = props.name,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn props(files: &[(&str, &str)]) -> (tempfile::TempDir, Properties) {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("BAT0")).unwrap();
+        for (name, value) in files {
+            std::fs::write(dir.path().join("BAT0").join(name), format!("{}\n", value)).unwrap();
+        }
+        let props = Properties::load(dir.path(), "BAT0").unwrap();
+
+        (dir, props)
+    }
+
+    #[test]
+    fn validates_well_known_attributes() {
+        let (_dir, props) = props(&[
+            ("charge_behaviour", "[auto] inhibit-charge"),
+            ("charge_control_limit_max", "3"),
+        ]);
+
+        assert_eq!(validate(&props, "charge_control_end_threshold", "80"), Ok(true));
+        assert!(validate(&props, "charge_control_end_threshold", "101").is_err());
+        assert!(validate(&props, "charge_control_end_threshold", "-1").is_err());
+        assert_eq!(validate(&props, "charge_control_limit", "3"), Ok(true));
+        assert!(validate(&props, "charge_control_limit", "4").is_err());
+        assert_eq!(validate(&props, "charge_behaviour", "inhibit-charge"), Ok(true));
+        assert_eq!(
+            validate(&props, "charge_behaviour", "force-discharge"),
+            Err("Invalid value 'force-discharge' for 'charge_behaviour', expected auto, inhibit-charge".to_string()),
+        );
+        assert_eq!(validate(&props, "charge_type", "Long Life"), Ok(true));
+        assert_eq!(validate(&props, "some_vendor_attribute", "1"), Ok(false));
+    }
+
+    #[test]
+    fn falls_back_to_the_abi_words_without_a_listing() {
+        let (_dir, props) = props(&[("charge_behaviour", "auto")]);
+
+        assert_eq!(describe(&props, "charge_behaviour").unwrap(), "auto, inhibit-charge, force-discharge");
+        assert_eq!(validate(&props, "charge_behaviour", "force-discharge"), Ok(true));
+        assert_eq!(describe(&props, "charge_control_limit").unwrap(), "0 or more");
+    }
+
+    #[test]
+    fn refuses_listing_attributes_without_a_listing() {
+        let (_dir, props) = props(&[("charge_types", "Fast"), ("usb_type", "[PD] PD_PPS")]);
+
+        assert_eq!(
+            validate(&props, "charge_types", "Fast"),
+            Err("'charge_types' of BAT0 doesn't list the values it accepts".to_string()),
+        );
+        assert_eq!(validate(&props, "usb_type", "PD_PPS"), Ok(true));
+    }
+}
@@ -0,0 +1,240 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! The battery module `bar` prints for status bars.
+
+use std::time::Duration;
+
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+
+use crate::battery::{Battery, Status, TimeLeft};
+use crate::config::Config;
+
+/// The status bars `bar` prints for.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BarFormat {
+    /// A JSON object for a waybar `custom` module with `return-type` set to `json`.
+    Waybar,
+    /// The full text, short text and color lines of an i3blocks block, or a
+    /// JSON object per line for `format=json` with `--continuous`.
+    I3blocks,
+    /// A line of text for a polybar `custom/script` module.
+    Polybar,
+    /// A JSON object for an i3status-rust `custom` block with `json = true`.
+    I3statusRust,
+}
+
+/// How urgent the module looks. Only the default level names are known;
+/// the capacity under other thresholds looks normal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Good,
+    Info,
+    Warning,
+    Critical,
+}
+
+impl State {
+    fn of(level: Option<&str>) -> Self {
+        match level {
+            Some("good") => State::Good,
+            Some("okay") => State::Info,
+            Some("bad") => State::Warning,
+            Some("critical") => State::Critical,
+            _ => State::Idle,
+        }
+    }
+
+    /// The color of i3blocks and polybar text, which have no classes to style.
+    fn color(self) -> Option<&'static str> {
+        match self {
+            State::Warning => Some("#FFB52A"),
+            State::Critical => Some("#FF5555"),
+            _ => None,
+        }
+    }
+}
+
+/// What a status bar shows about a battery.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub device: String,
+    pub capacity: Option<u8>,
+    pub status: Option<Status>,
+    /// The name of the configured threshold the capacity sits under, like `critical`.
+    pub level: Option<String>,
+    pub time_left: TimeLeft,
+    pub power_w: Option<f64>,
+}
+
+impl Module {
+    pub fn of(bat: &Battery, config: &Config, time_left: TimeLeft) -> Self {
+        Self {
+            device: bat.name.clone(),
+            capacity: bat.capacity,
+            status: bat.status,
+            level: bat.capacity
+                .and_then(|c| config.level(&bat.name, c))
+                .map(|t| t.name.clone()),
+            time_left,
+            power_w: bat.power_w(),
+        }
+    }
+
+    /// The capacity, a `+` while charging and the time left, e.g. `64%+ 1:05`.
+    pub fn text(&self) -> String {
+        let mut text = self.short_text();
+        if self.status == Some(Status::Charging) {
+            text.push('+');
+        }
+        if let Some(time) = self.time_left.to_empty.or(self.time_left.to_full) {
+            text.push_str(&format!(" {}", clock(time)));
+        }
+        text
+    }
+
+    fn short_text(&self) -> String {
+        match self.capacity {
+            Some(capacity) => format!("{}%", capacity),
+            None => "-".to_string(),
+        }
+    }
+
+    pub fn tooltip(&self) -> String {
+        let mut lines = vec![match self.status {
+            Some(status) => format!("{}: {}", self.device, status),
+            None => self.device.clone(),
+        }];
+        if let Some(time) = self.time_left.to_empty {
+            lines.push(format!("{} to empty", clock(time)));
+        }
+        if let Some(time) = self.time_left.to_full {
+            lines.push(format!("{} to full", clock(time)));
+        }
+        if let Some(power) = self.power_w {
+            lines.push(format!("{:.2} W", power));
+        }
+        lines.join("\n")
+    }
+
+    /// The level and the status in kebab case, e.g. `["critical", "discharging"]`.
+    pub fn classes(&self) -> Vec<String> {
+        self.level
+            .iter()
+            .cloned()
+            .chain(self.status.map(|s| s.to_string().to_lowercase().replace(' ', "-")))
+            .collect()
+    }
+
+    /// Formats the module for a status bar. Bars reading a stream take one
+    /// update per line, which `continuous` asks for.
+    pub fn render(&self, format: BarFormat, continuous: bool) -> String {
+        let state = State::of(self.level.as_deref());
+
+        match format {
+            BarFormat::Waybar => {
+                let mut module = json!({
+                    "text": self.text(),
+                    "tooltip": self.tooltip(),
+                    "class": self.classes(),
+                });
+                if let Some(capacity) = self.capacity {
+                    module["percentage"] = json!(capacity);
+                }
+                module.to_string()
+            }
+            BarFormat::I3blocks if continuous => {
+                let mut block = Map::new();
+                block.insert("full_text".to_string(), json!(self.text()));
+                block.insert("short_text".to_string(), json!(self.short_text()));
+                if let Some(color) = state.color() {
+                    block.insert("color".to_string(), json!(color));
+                }
+                Value::Object(block).to_string()
+            }
+            BarFormat::I3blocks => {
+                let mut lines = vec![self.text(), self.short_text()];
+                lines.extend(state.color().map(String::from));
+                lines.join("\n")
+            }
+            BarFormat::Polybar => match state.color() {
+                Some(color) => format!("%{{F{}}}{}%{{F-}}", color, self.text()),
+                None => self.text(),
+            },
+            BarFormat::I3statusRust => json!({
+                "text": self.text(),
+                "short_text": self.short_text(),
+                "state": format!("{:?}", state),
+            }).to_string(),
+        }
+    }
+}
+
+/// Formats a duration as hours and minutes, e.g. `2:05`.
+fn clock(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(capacity: u8, status: Status) -> Module {
+        let bat = Battery {
+            name: "BAT0".to_string(),
+            capacity: Some(capacity),
+            status: Some(status),
+            energy_now: Some(10_000_000),
+            energy_full: Some(50_000_000),
+            power_now: Some(8_000_000),
+            ..Battery::default()
+        };
+        Module::of(&bat, &Config::new(), TimeLeft::of(&bat))
+    }
+
+    #[test]
+    fn maps_the_configured_levels_to_classes() {
+        assert_eq!(module(90, Status::Full).classes(), ["full"]);
+        assert_eq!(module(60, Status::Charging).classes(), ["good", "charging"]);
+        assert_eq!(module(4, Status::NotCharging).classes(), ["critical", "not-charging"]);
+    }
+
+    #[test]
+    fn renders_each_bar_format() {
+        let module = module(18, Status::Discharging);
+
+        assert_eq!(
+            module.render(BarFormat::Waybar, false),
+            r#"{"class":["bad","discharging"],"percentage":18,"text":"18% 1:15","tooltip":"BAT0: Discharging\n1:15 to empty\n8.00 W"}"#,
+        );
+        assert_eq!(module.render(BarFormat::I3blocks, false), "18% 1:15\n18%\n#FFB52A");
+        assert_eq!(
+            module.render(BarFormat::I3blocks, true),
+            r##"{"color":"#FFB52A","full_text":"18% 1:15","short_text":"18%"}"##,
+        );
+        assert_eq!(module.render(BarFormat::Polybar, false), "%{F#FFB52A}18% 1:15%{F-}");
+        assert_eq!(
+            module.render(BarFormat::I3statusRust, false),
+            r#"{"short_text":"18%","state":"Warning","text":"18% 1:15"}"#,
+        );
+    }
+}
@@ -17,13 +17,18 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use std::{fmt, fs, path::PathBuf};
+use std::{collections::{BTreeMap, HashMap, VecDeque}, ffi::CString, fmt, fs, io, path::{Path, PathBuf}, str::FromStr, time::{Duration, Instant}};
+use std::os::unix::{ffi::OsStringExt, fs::PermissionsExt};
+
+use clap::ValueEnum;
 
-#[allow(dead_code)]
 #[derive(Debug)]
 pub enum BatteryError {
   IOError(String),
   ParseError(String),
+  MissingProperty(String),
+  PermissionDenied(String),
+  Rejected(String),
 }
 
 impl fmt::Display for BatteryError {
@@ -31,6 +36,9 @@ impl fmt::Display for BatteryError {
     match self {
       BatteryError::IOError(e) => write!(f, "{}", e),
       BatteryError::ParseError(s) => write!(f, "{}", s),
+      BatteryError::MissingProperty(s) => write!(f, "The device doesn't expose the '{}' property", s),
+      BatteryError::PermissionDenied(s) => write!(f, "Permission denied writing '{}', try running as root", s),
+      BatteryError::Rejected(s) => write!(f, "The driver rejected {}", s),
     }
   }
 }
@@ -39,33 +47,604 @@ impl std::error::Error for BatteryError { }
 
 pub const BATTERIES_DIRECTORY: &str = "/sys/class/power_supply/";
 
-#[allow(dead_code)]
-pub fn get_device_property_raw(battery_name: &String, property_name: &str) -> Result<String, BatteryError> {
-  let path: String = PathBuf::from(BATTERIES_DIRECTORY)
-    .join(battery_name)
-    .join(property_name)
-    .to_string_lossy()
-    .into_owned();
-  match fs::read_to_string(path) {
-    Ok(content) => Ok(content[..content.len() - 1].to_string()),
-    Err(e) => Err(BatteryError::IOError(e.to_string())),
+/// The environment variable that overrides [`BATTERIES_DIRECTORY`].
+pub const SYSFS_ROOT_ENV: &str = "BATTERYCTL_SYSFS_ROOT";
+
+/// The charging status, as reported by the `status` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+  Unknown,
+  Charging,
+  Discharging,
+  NotCharging,
+  Full,
+}
+
+impl FromStr for Status {
+  type Err = BatteryError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "Unknown" => Ok(Status::Unknown),
+      "Charging" => Ok(Status::Charging),
+      "Discharging" => Ok(Status::Discharging),
+      "Not charging" => Ok(Status::NotCharging),
+      "Full" => Ok(Status::Full),
+      _ => Err(BatteryError::ParseError(format!("Unknown battery status '{}'", s))),
+    }
   }
 }
 
-#[allow(dead_code)]
-pub fn get_devices() -> Result<Vec<String>, BatteryError> {
-  let dirs: fs::ReadDir;
-  match fs::read_dir(BATTERIES_DIRECTORY) {
-    Ok(val) => dirs = val,
-    Err(e) => return Err(BatteryError::IOError(e.to_string())),
+impl fmt::Display for Status {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Status::Unknown => write!(f, "Unknown"),
+      Status::Charging => write!(f, "Charging"),
+      Status::Discharging => write!(f, "Discharging"),
+      Status::NotCharging => write!(f, "Not charging"),
+      Status::Full => write!(f, "Full"),
+    }
+  }
+}
+
+/// The coarse charge level, as reported by the `capacity_level` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapacityLevel {
+  Unknown,
+  Critical,
+  Low,
+  Normal,
+  High,
+  Full,
+}
+
+impl FromStr for CapacityLevel {
+  type Err = BatteryError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "Unknown" => Ok(CapacityLevel::Unknown),
+      "Critical" => Ok(CapacityLevel::Critical),
+      "Low" => Ok(CapacityLevel::Low),
+      "Normal" => Ok(CapacityLevel::Normal),
+      "High" => Ok(CapacityLevel::High),
+      "Full" => Ok(CapacityLevel::Full),
+      _ => Err(BatteryError::ParseError(format!("Unknown capacity level '{}'", s))),
+    }
+  }
+}
+
+impl fmt::Display for CapacityLevel {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CapacityLevel::Unknown => write!(f, "Unknown"),
+      CapacityLevel::Critical => write!(f, "Critical"),
+      CapacityLevel::Low => write!(f, "Low"),
+      CapacityLevel::Normal => write!(f, "Normal"),
+      CapacityLevel::High => write!(f, "High"),
+      CapacityLevel::Full => write!(f, "Full"),
+    }
+  }
+}
+
+/// A battery loaded from its sysfs device directory.
+///
+/// Units follow the kernel's power_supply ABI: energy in µWh, charge in µAh,
+/// voltage in µV, current in µA and power in µW. Attributes the driver
+/// doesn't expose are `None`.
+#[derive(Debug, Clone, Default)]
+pub struct Battery {
+  pub name: String,
+  pub status: Option<Status>,
+  pub capacity: Option<u8>,
+  pub capacity_level: Option<CapacityLevel>,
+  pub energy_now: Option<u64>,
+  pub energy_full: Option<u64>,
+  pub energy_full_design: Option<u64>,
+  pub charge_now: Option<u64>,
+  pub charge_full: Option<u64>,
+  pub charge_full_design: Option<u64>,
+  pub voltage_now: Option<u64>,
+  pub voltage_min_design: Option<u64>,
+  pub current_now: Option<i64>,
+  pub power_now: Option<i64>,
+  pub cycle_count: Option<u32>,
+  /// The temperature in tenths of a degree Celsius.
+  pub temp: Option<i32>,
+  pub technology: Option<String>,
+  pub manufacturer: Option<String>,
+  pub model_name: Option<String>,
+  pub serial_number: Option<String>,
+  pub manufacture_year: Option<u16>,
+  pub manufacture_month: Option<u8>,
+  pub manufacture_day: Option<u8>,
+}
+
+impl Battery {
+  pub fn load(root: &Path, battery_name: &str) -> Result<Self, BatteryError> {
+    Self::from_properties(&Properties::load(root, battery_name)?)
+  }
+
+  pub fn from_properties(props: &Properties) -> Result<Self, BatteryError> {
+    Ok(Self {
+      name: props.name.clone(),
+      status: props.parsed("status")?,
+      capacity: props.parsed("capacity")?,
+      capacity_level: props.parsed("capacity_level")?,
+      energy_now: props.parsed("energy_now")?,
+      energy_full: props.parsed("energy_full")?,
+      energy_full_design: props.parsed("energy_full_design")?,
+      charge_now: props.parsed("charge_now")?,
+      charge_full: props.parsed("charge_full")?,
+      charge_full_design: props.parsed("charge_full_design")?,
+      voltage_now: props.parsed("voltage_now")?,
+      voltage_min_design: props.parsed("voltage_min_design")?,
+      current_now: props.parsed("current_now")?,
+      power_now: props.parsed("power_now")?,
+      cycle_count: props.parsed("cycle_count")?,
+      temp: props.parsed("temp")?,
+      technology: props.get("technology")?,
+      manufacturer: props.get("manufacturer")?,
+      model_name: props.get("model_name")?,
+      serial_number: props.get("serial_number")?,
+      manufacture_year: props.parsed("manufacture_year")?,
+      manufacture_month: props.parsed("manufacture_month")?,
+      manufacture_day: props.parsed("manufacture_day")?,
+    })
+  }
+
+  /// Returns the value of a modelled property formatted the way sysfs prints it,
+  /// or `None` if the property isn't modelled or the driver doesn't expose it.
+  pub fn property(&self, property_name: &str) -> Option<String> {
+    match property_name {
+      "status" => self.status.map(|v| v.to_string()),
+      "capacity" => self.capacity.map(|v| v.to_string()),
+      "capacity_level" => self.capacity_level.map(|v| v.to_string()),
+      "energy_now" => self.energy_now.map(|v| v.to_string()),
+      "energy_full" => self.energy_full.map(|v| v.to_string()),
+      "energy_full_design" => self.energy_full_design.map(|v| v.to_string()),
+      "charge_now" => self.charge_now.map(|v| v.to_string()),
+      "charge_full" => self.charge_full.map(|v| v.to_string()),
+      "charge_full_design" => self.charge_full_design.map(|v| v.to_string()),
+      "voltage_now" => self.voltage_now.map(|v| v.to_string()),
+      "voltage_min_design" => self.voltage_min_design.map(|v| v.to_string()),
+      "current_now" => self.current_now.map(|v| v.to_string()),
+      "power_now" => self.power_now.map(|v| v.to_string()),
+      "cycle_count" => self.cycle_count.map(|v| v.to_string()),
+      "temp" => self.temp.map(|v| v.to_string()),
+      "technology" => self.technology.clone(),
+      "manufacturer" => self.manufacturer.clone(),
+      "model_name" => self.model_name.clone(),
+      "serial_number" => self.serial_number.clone(),
+      "manufacture_year" => self.manufacture_year.map(|v| v.to_string()),
+      "manufacture_month" => self.manufacture_month.map(|v| v.to_string()),
+      "manufacture_day" => self.manufacture_day.map(|v| v.to_string()),
+      _ => None,
+    }
+  }
+
+  /// Returns the capacity, failing if the driver doesn't expose it.
+  pub fn require_capacity(&self) -> Result<u8, BatteryError> {
+    self.capacity.ok_or_else(|| BatteryError::MissingProperty("capacity".to_string()))
+  }
+
+  /// The power flowing in or out of the battery in watts, from `power_now` or
+  /// `current_now` × `voltage_now`. Some drivers report negative values while
+  /// discharging, so this is always the magnitude.
+  pub fn power_w(&self) -> Option<f64> {
+    match (self.power_now, self.current_now, self.voltage_now) {
+      (Some(power), _, _) => Some(power.unsigned_abs() as f64 / 1e6),
+      (None, Some(current), Some(voltage)) => Some(current.unsigned_abs() as f64 / 1e6 * voltage as f64 / 1e6),
+      _ => None,
+    }
+  }
+
+  pub fn energy_wh(&self) -> Option<f64> {
+    match (self.energy_now, self.charge_now, self.voltage_now) {
+      (Some(energy), _, _) => Some(energy as f64 / 1e6),
+      (None, Some(charge), Some(voltage)) => Some(charge as f64 / 1e6 * voltage as f64 / 1e6),
+      _ => None,
+    }
+  }
+
+  /// The energy when full in watt-hours, converting charge at the current voltage like `energy_wh`.
+  pub fn energy_full_wh(&self) -> Option<f64> {
+    match (self.energy_full, self.charge_full, self.voltage_now) {
+      (Some(energy), _, _) => Some(energy as f64 / 1e6),
+      (None, Some(charge), Some(voltage)) => Some(charge as f64 / 1e6 * voltage as f64 / 1e6),
+      _ => None,
+    }
   }
+
+  pub fn voltage_v(&self) -> Option<f64> {
+    self.voltage_now.map(|v| v as f64 / 1e6)
+  }
+
+  /// How long the battery lasts at the current draw, if it's discharging.
+  pub fn time_to_empty(&self) -> Option<Duration> {
+    if self.status != Some(Status::Discharging) {
+      return None;
+    }
+
+    let hours = match (self.energy_now, self.power_now, self.charge_now, self.current_now) {
+      (Some(energy), Some(power), _, _) if power != 0 => energy as f64 / power.unsigned_abs() as f64,
+      (_, _, Some(charge), Some(current)) if current != 0 => charge as f64 / current.unsigned_abs() as f64,
+      _ => return None,
+    };
+
+    Some(Duration::from_secs_f64(hours * 3600.0))
+  }
+
+  /// How long until the battery is full at the current charge rate, if it's charging.
+  pub fn time_to_full(&self) -> Option<Duration> {
+    if self.status != Some(Status::Charging) {
+      return None;
+    }
+
+    let hours = match (self.energy_now, self.energy_full, self.power_now, self.charge_now, self.charge_full, self.current_now) {
+      (Some(now), Some(full), Some(power), _, _, _) if power != 0 =>
+        full.saturating_sub(now) as f64 / power.unsigned_abs() as f64,
+      (_, _, _, Some(now), Some(full), Some(current)) if current != 0 =>
+        full.saturating_sub(now) as f64 / current.unsigned_abs() as f64,
+      _ => return None,
+    };
+
+    Some(Duration::from_secs_f64(hours * 3600.0))
+  }
+}
+
+/// How long capacity samples are kept for the smoothed rate.
+const RATE_WINDOW: Duration = Duration::from_secs(30 * 60);
+
+/// The estimated time until a battery is empty or full. At most one of them is known.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeLeft {
+  pub to_empty: Option<Duration>,
+  pub to_full: Option<Duration>,
+}
+
+impl TimeLeft {
+  /// Estimates the time left from the current power or current draw.
+  pub fn of(bat: &Battery) -> Self {
+    Self {
+      to_empty: bat.time_to_empty(),
+      to_full: bat.time_to_full(),
+    }
+  }
+}
+
+/// Estimates the time left of a battery over successive readings.
+///
+/// The power or current draw is used when the driver reports it. Otherwise the
+/// estimate comes from the average capacity change over the last half hour,
+/// which smooths out the whole-percent steps of `capacity`.
+#[derive(Debug, Default)]
+pub struct Estimator {
+  samples: VecDeque<(Instant, u8)>,
+  status: Option<Status>,
+}
+
+impl Estimator {
+  pub fn update(&mut self, bat: &Battery, now: Instant) -> TimeLeft {
+    // The rate before a status change says nothing about the rate after it.
+    if bat.status != self.status {
+      self.samples.clear();
+      self.status = bat.status;
+    }
+    if let Some(capacity) = bat.capacity {
+      self.samples.push_back((now, capacity));
+    }
+    while self.samples.len() > 2 && self.samples.front().is_some_and(|(t, _)| now.duration_since(*t) > RATE_WINDOW) {
+      self.samples.pop_front();
+    }
+
+    let instant = TimeLeft::of(bat);
+    if instant != TimeLeft::default() {
+      return instant;
+    }
+
+    let (rate, capacity) = match (self.rate(), bat.capacity) {
+      (Some(rate), Some(capacity)) => (rate, capacity as f64),
+      _ => return instant,
+    };
+    let hours = |percent: f64, rate: f64| Some(Duration::from_secs_f64(percent / rate * 3600.0));
+    match bat.status {
+      Some(Status::Discharging) if rate < 0.0 => TimeLeft { to_empty: hours(capacity, -rate), to_full: None },
+      Some(Status::Charging) if rate > 0.0 => TimeLeft { to_empty: None, to_full: hours(100.0 - capacity, rate) },
+      _ => instant,
+    }
+  }
+
+  /// The average capacity change over the samples in percent per hour.
+  fn rate(&self) -> Option<f64> {
+    let (first, last) = (self.samples.front()?, self.samples.back()?);
+    let hours = last.0.duration_since(first.0).as_secs_f64() / 3600.0;
+    if hours == 0.0 {
+      return None;
+    }
+
+    Some((last.1 as f64 - first.1 as f64) / hours)
+  }
+}
+
+/// The kind of power supply, as reported by the `type` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DeviceType {
+  Battery,
+  Mains,
+  Usb,
+  Ups,
+  Wireless,
+  Unknown,
+}
+
+impl FromStr for DeviceType {
+  type Err = BatteryError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "Battery" => Ok(DeviceType::Battery),
+      "Mains" => Ok(DeviceType::Mains),
+      // Older kernels report the USB charger flavour in the type itself.
+      "USB" | "USB_DCP" | "USB_CDP" | "USB_ACA" | "USB_C" | "USB_PD" | "USB_PD_DRP" => Ok(DeviceType::Usb),
+      "UPS" => Ok(DeviceType::Ups),
+      "Wireless" => Ok(DeviceType::Wireless),
+      "Unknown" | "BrickID" => Ok(DeviceType::Unknown),
+      _ => Err(BatteryError::ParseError(format!("Unknown device type '{}'", s))),
+    }
+  }
+}
+
+impl fmt::Display for DeviceType {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      DeviceType::Battery => write!(f, "Battery"),
+      DeviceType::Mains => write!(f, "Mains"),
+      DeviceType::Usb => write!(f, "USB"),
+      DeviceType::Ups => write!(f, "UPS"),
+      DeviceType::Wireless => write!(f, "Wireless"),
+      DeviceType::Unknown => write!(f, "Unknown"),
+    }
+  }
+}
+
+/// Whether a device powers the system or a peripheral, as reported by the `scope` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Scope {
+  System,
+  Device,
+  Unknown,
+}
+
+impl FromStr for Scope {
+  type Err = BatteryError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "System" => Ok(Scope::System),
+      "Device" => Ok(Scope::Device),
+      "Unknown" => Ok(Scope::Unknown),
+      _ => Err(BatteryError::ParseError(format!("Unknown device scope '{}'", s))),
+    }
+  }
+}
+
+impl fmt::Display for Scope {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Scope::System => write!(f, "System"),
+      Scope::Device => write!(f, "Device"),
+      Scope::Unknown => write!(f, "Unknown"),
+    }
+  }
+}
+
+/// A power_supply device of any type, with its classification.
+#[derive(Debug, Clone)]
+pub struct Device {
+  pub name: String,
+  pub device_type: DeviceType,
+  /// `None` when the driver doesn't report a scope, which the kernel treats as `System`.
+  pub scope: Option<Scope>,
+  pub online: Option<bool>,
+  pub present: Option<bool>,
+  pub properties: Properties,
+}
+
+impl Device {
+  pub fn load(root: &Path, device_name: &str) -> Result<Self, BatteryError> {
+    Self::from_properties(Properties::load(root, device_name)?)
+  }
+
+  pub fn from_properties(props: Properties) -> Result<Self, BatteryError> {
+    Ok(Self {
+      name: props.name.clone(),
+      // Types and scopes newer kernels add are still devices, just unknown ones.
+      device_type: match props.parsed("type") {
+        Err(BatteryError::ParseError(_)) => DeviceType::Unknown,
+        result => result?.unwrap_or(DeviceType::Unknown),
+      },
+      scope: match props.parsed("scope") {
+        Err(BatteryError::ParseError(_)) => Some(Scope::Unknown),
+        result => result?,
+      },
+      online: props.parsed::<u8>("online")?.map(|v| v != 0),
+      present: props.parsed::<u8>("present")?.map(|v| v != 0),
+      properties: props,
+    })
+  }
+
+  pub fn effective_scope(&self) -> Scope {
+    self.scope.unwrap_or(Scope::System)
+  }
+}
+
+/// The properties of a device, read from its `uevent` file in one go.
+///
+/// Properties missing from `uevent` (some drivers only export a subset there)
+/// are read from their individual attribute files instead.
+#[derive(Debug, Clone)]
+pub struct Properties {
+  pub name: String,
+  dir: PathBuf,
+  uevent: HashMap<String, String>,
+}
+
+impl Properties {
+  pub fn load(root: &Path, device_name: &str) -> Result<Self, BatteryError> {
+    let dir = root.join(device_name);
+    if !dir.is_dir() {
+      return Err(BatteryError::IOError(format!("No such device: '{}'", device_name)));
+    }
+
+    let uevent = match read_optional(&dir, "uevent")? {
+      Some(contents) => parse_uevent(&contents),
+      None => HashMap::new(),
+    };
+
+    Ok(Self {
+      name: device_name.to_string(),
+      dir,
+      uevent,
+    })
+  }
+
+  pub fn get(&self, property_name: &str) -> Result<Option<String>, BatteryError> {
+    match self.uevent.get(property_name) {
+      Some(val) => Ok(Some(val.clone())),
+      None => read_optional(&self.dir, property_name),
+    }
+  }
+
+  pub fn parsed<T: FromStr>(&self, property_name: &str) -> Result<Option<T>, BatteryError> {
+    match self.get(property_name)? {
+      Some(raw) => match raw.trim().parse::<T>() {
+        Ok(val) => Ok(Some(val)),
+        Err(_) => Err(BatteryError::ParseError(format!("Invalid value '{}' for property '{}'", raw, property_name))),
+      },
+      None => Ok(None),
+    }
+  }
+
+  /// Reads an attribute from its own file, skipping `uevent`. Attributes that
+  /// can be written are read this way, since the uevent may predate a write.
+  pub fn read_attribute(&self, property_name: &str) -> Result<Option<String>, BatteryError> {
+    read_optional(&self.dir, property_name)
+  }
+
+  /// Writes an attribute the way `echo value > attribute` would.
+  pub fn write(&self, property_name: &str, value: &str) -> Result<(), BatteryError> {
+    let result = fs::OpenOptions::new()
+      .write(true)
+      .truncate(true)
+      .open(self.dir.join(property_name))
+      .and_then(|mut file| io::Write::write_all(&mut file, value.as_bytes()));
+
+    match result {
+      Ok(_) => Ok(()),
+      Err(e) => Err(match e.kind() {
+        io::ErrorKind::NotFound => BatteryError::MissingProperty(property_name.to_string()),
+        io::ErrorKind::PermissionDenied => BatteryError::PermissionDenied(property_name.to_string()),
+        io::ErrorKind::InvalidInput => BatteryError::Rejected(format!("'{}' for '{}'", value, property_name)),
+        _ => BatteryError::IOError(format!("{}: {}", property_name, e)),
+      }),
+    }
+  }
+
+  /// The path of an attribute file.
+  pub fn path(&self, property_name: &str) -> PathBuf {
+    self.dir.join(property_name)
+  }
+
+  /// Returns the attributes whose mode has a write bit set, sorted by name.
+  pub fn writable(&self) -> Result<Vec<String>, BatteryError> {
+    let entries = match fs::read_dir(&self.dir) {
+      Ok(val) => val,
+      Err(e) => return Err(BatteryError::IOError(e.to_string())),
+    };
+    let mut names: Vec<String> = entries
+      .flatten()
+      .filter(|entry| entry.file_name() != "uevent")
+      .filter(|entry| entry.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o222 != 0))
+      .map(|entry| entry.file_name().to_string_lossy().into_owned())
+      .collect();
+    names.sort();
+
+    Ok(names)
+  }
+
+  /// Whether the current user may write an attribute, without writing it.
+  pub fn can_write(&self, property_name: &str) -> bool {
+    let path = match CString::new(self.path(property_name).into_os_string().into_vec()) {
+      Ok(val) => val,
+      Err(_) => return false,
+    };
+
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+  }
+
+  /// Returns every readable property, merging `uevent` with the attribute files.
+  pub fn all(&self) -> Result<BTreeMap<String, String>, BatteryError> {
+    let mut props: BTreeMap<String, String> = self.uevent
+      .iter()
+      .map(|(k, v)| (k.clone(), v.clone()))
+      .collect();
+
+    let entries = match fs::read_dir(&self.dir) {
+      Ok(val) => val,
+      Err(e) => return Err(BatteryError::IOError(e.to_string())),
+    };
+    for entry in entries.flatten() {
+      let name = entry.file_name().to_string_lossy().into_owned();
+      if name == "uevent" || props.contains_key(&name) {
+        continue;
+      }
+      // Skips the `device`, `subsystem` and `power` links and directories.
+      match entry.file_type() {
+        Ok(t) if t.is_file() => (),
+        _ => continue,
+      }
+      // Write-only or unsupported attributes fail to read; they aren't properties.
+      if let Ok(val) = fs::read_to_string(entry.path()) {
+        props.insert(name, val.trim_end().to_string());
+      }
+    }
+
+    Ok(props)
+  }
+}
+
+/// Parses the `POWER_SUPPLY_*=value` lines of a uevent file into lowercase
+/// property names, e.g. `POWER_SUPPLY_CAPACITY=87` becomes `capacity`.
+pub fn parse_uevent(contents: &str) -> HashMap<String, String> {
+  contents
+    .lines()
+    .filter_map(|line| line.strip_prefix("POWER_SUPPLY_"))
+    .filter_map(|line| line.split_once('='))
+    .map(|(key, val)| (key.to_lowercase(), val.to_string()))
+    .collect()
+}
+
+/// Reads a single attribute, returning `None` if the file doesn't exist.
+fn read_optional(dir: &Path, property_name: &str) -> Result<Option<String>, BatteryError> {
+  match fs::read_to_string(dir.join(property_name)) {
+    Ok(content) => Ok(Some(content.trim_end().to_string())),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+    Err(e) => Err(BatteryError::IOError(format!("{}: {}", property_name, e))),
+  }
+}
+
+pub fn get_devices(root: &Path) -> Result<Vec<String>, BatteryError> {
+  let dirs = match fs::read_dir(root) {
+    Ok(val) => val,
+    Err(e) => return Err(BatteryError::IOError(e.to_string())),
+  };
   let mut devices = Vec::new();
   for dir in dirs {
-    let entry: fs::DirEntry;
-    match dir {
-      Ok(val) => entry = val,
+    let entry = match dir {
+      Ok(val) => val,
       Err(e) => return Err(BatteryError::IOError(e.to_string())),
-    }
+    };
     match entry.path().file_name() {
       Some(val) => devices.push(val
         .to_string_lossy()
@@ -73,6 +652,69 @@ pub fn get_devices() -> Result<Vec<String>, BatteryError> {
       None => return Err(BatteryError::IOError("File name is None".to_string())),
     }
   }
+  devices.sort();
   Ok(devices)
 }
 
+/// Loads and classifies every device under the sysfs root. A device that
+/// can't be read is skipped rather than hiding all the others.
+pub fn load_devices(root: &Path) -> Result<Vec<Device>, BatteryError> {
+  let devices = get_devices(root)?
+    .iter()
+    .filter_map(|name| match Device::load(root, name) {
+      Ok(val) => Some(val),
+      Err(e) => {
+        eprintln!("[WARN] Skipping {}: {}", name, e);
+        None
+      }
+    })
+    .collect();
+
+  Ok(devices)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn battery(status: Status, capacity: u8) -> Battery {
+    let props = Properties {
+      name: "BAT0".to_string(),
+      dir: PathBuf::from("/nonexistent"),
+      uevent: HashMap::from([
+        ("status".to_string(), status.to_string()),
+        ("capacity".to_string(), capacity.to_string()),
+      ]),
+    };
+    Battery::from_properties(&props).unwrap()
+  }
+
+  #[test]
+  fn estimates_from_the_power_draw() {
+    let mut bat = battery(Status::Discharging, 50);
+    bat.energy_now = Some(30_000_000);
+    bat.power_now = Some(-10_000_000);
+
+    let time_left = Estimator::default().update(&bat, Instant::now());
+
+    assert_eq!(time_left, TimeLeft { to_empty: Some(Duration::from_secs(3 * 3600)), to_full: None });
+  }
+
+  #[test]
+  fn estimates_from_the_capacity_rate_without_a_power_draw() {
+    let mut estimator = Estimator::default();
+    let start = Instant::now();
+    let minutes = |m: u64| start + Duration::from_secs(m * 60);
+
+    assert_eq!(estimator.update(&battery(Status::Discharging, 60), minutes(0)), TimeLeft::default());
+    estimator.update(&battery(Status::Discharging, 59), minutes(3));
+    let time_left = estimator.update(&battery(Status::Discharging, 58), minutes(6));
+    // 2% in 6 minutes leaves 58% for 174 minutes.
+    assert_eq!(time_left.to_empty, Some(Duration::from_secs(174 * 60)));
+
+    // Plugging in starts over.
+    assert_eq!(estimator.update(&battery(Status::Charging, 58), minutes(7)), TimeLeft::default());
+    let time_left = estimator.update(&battery(Status::Charging, 60), minutes(9));
+    assert_eq!(time_left.to_full, Some(Duration::from_secs(40 * 60)));
+  }
+}
@@ -0,0 +1,288 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::{fmt, path::Path, str::FromStr};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::battery::{BatteryError, Properties};
+use crate::config::ChargeLimit;
+use crate::helper::{self, HelperError};
+
+const START_THRESHOLD: &str = "charge_control_start_threshold";
+const END_THRESHOLD: &str = "charge_control_end_threshold";
+const BEHAVIOUR: &str = "charge_behaviour";
+
+/// What the battery does while the charger is connected, as the `charge_behaviour` attribute sets it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChargeBehaviour {
+    Auto,
+    InhibitCharge,
+    ForceDischarge,
+}
+
+impl fmt::Display for ChargeBehaviour {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChargeBehaviour::Auto => write!(f, "auto"),
+            ChargeBehaviour::InhibitCharge => write!(f, "inhibit-charge"),
+            ChargeBehaviour::ForceDischarge => write!(f, "force-discharge"),
+        }
+    }
+}
+
+impl FromStr for ChargeBehaviour {
+    type Err = BatteryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ChargeBehaviour::Auto),
+            "inhibit-charge" => Ok(ChargeBehaviour::InhibitCharge),
+            "force-discharge" => Ok(ChargeBehaviour::ForceDischarge),
+            _ => Err(BatteryError::ParseError(format!("Unknown charge behaviour '{}'", s))),
+        }
+    }
+}
+
+/// The charge control attributes of a battery. The ones its driver doesn't support are `None`.
+pub struct ChargeControl {
+    props: Properties,
+    pub start: Option<u8>,
+    pub end: Option<u8>,
+    /// The selected behaviour, `None` if it's one batteryctl doesn't know,
+    /// like the `inhibit-charge-awake` of newer kernels.
+    pub behaviour: Option<ChargeBehaviour>,
+    /// The known behaviours the driver accepts, if it lists them.
+    pub available: Option<Vec<ChargeBehaviour>>,
+    /// Whether the driver has a charge behaviour at all.
+    supports_behaviour: bool,
+}
+
+impl ChargeControl {
+    pub fn load(root: &Path, battery_name: &str) -> Result<Self, BatteryError> {
+        let props = Properties::load(root, battery_name)?;
+        let threshold = |name: &str| -> Result<Option<u8>, BatteryError> {
+            match props.read_attribute(name)? {
+                Some(raw) => raw
+                    .trim()
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| BatteryError::ParseError(format!("Invalid value '{}' for property '{}'", raw, name))),
+                None => Ok(None),
+            }
+        };
+        let start = threshold(START_THRESHOLD)?;
+        let end = threshold(END_THRESHOLD)?;
+
+        // Lists every behaviour with the selected one in brackets, e.g. `[auto] inhibit-charge`.
+        // Newer kernels add behaviours, which mustn't stop the thresholds from working.
+        let raw_behaviour = props.read_attribute(BEHAVIOUR)?;
+        let (behaviour, available) = match &raw_behaviour {
+            Some(raw) if raw.contains('[') => {
+                let selected = raw
+                    .split_whitespace()
+                    .find_map(|word| word.strip_prefix('[').and_then(|w| w.strip_suffix(']')));
+                let available = raw
+                    .split_whitespace()
+                    .filter_map(|word| word.trim_matches(['[', ']']).parse().ok())
+                    .collect();
+                (selected.and_then(|s| s.parse().ok()), Some(available))
+            }
+            Some(raw) => (raw.trim().parse().ok(), None),
+            None => (None, None),
+        };
+
+        Ok(Self {
+            props,
+            start,
+            end,
+            behaviour,
+            available,
+            supports_behaviour: raw_behaviour.is_some(),
+        })
+    }
+
+    /// Checks a limit against the thresholds it leaves in place and against
+    /// what the driver supports.
+    pub fn validate(&self, limit: &ChargeLimit) -> Result<(), String> {
+        let name = &self.props.name;
+
+        limit.validate()?;
+        if limit.start.is_some() && self.start.is_none() {
+            return Err(format!("{} doesn't support a charge start threshold", name));
+        }
+        if limit.end.is_some() && self.end.is_none() {
+            return Err(format!("{} doesn't support a charge end threshold", name));
+        }
+        if let Some(behaviour) = limit.behaviour {
+            match &self.available {
+                _ if !self.supports_behaviour => return Err(format!("{} doesn't support setting the charge behaviour", name)),
+                Some(available) if !available.contains(&behaviour) => {
+                    let available: Vec<String> = available.iter().map(|b| b.to_string()).collect();
+                    return Err(format!("{} doesn't support the '{}' charge behaviour, only {}", name, behaviour, available.join(", ")));
+                }
+                _ => (),
+            }
+        }
+
+        if let (Some(start), Some(end)) = (limit.start.or(self.start), limit.end.or(self.end)) {
+            if start >= end {
+                return Err(format!("The charge start threshold ({}%) has to be below the end threshold ({}%)", start, end));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the driver supports any of the charge control attributes.
+    pub fn is_supported(&self) -> bool {
+        self.start.is_some() || self.end.is_some() || self.supports_behaviour
+    }
+
+    /// Whether applying the limit would change anything.
+    pub fn differs(&self, limit: &ChargeLimit) -> bool {
+        limit.start.is_some_and(|s| Some(s) != self.start)
+            || limit.end.is_some_and(|e| Some(e) != self.end)
+            || limit.behaviour.is_some_and(|b| Some(b) != self.behaviour)
+    }
+
+    /// Writes the parts of the limit that differ from the current values,
+    /// through the privileged helper if batteryctl isn't allowed to. The
+    /// helper may ask for a password only if `interactive`.
+    pub fn apply(&self, limit: &ChargeLimit, interactive: bool) -> Result<(), String> {
+        let error = match self.apply_with(limit, |name, value| self.props.write(name, value)) {
+            Err(e @ BatteryError::PermissionDenied(_)) => e,
+            result => return result.map_err(|e| e.to_string()),
+        };
+
+        match self.apply_with(limit, |name, value| helper::set_property(&self.props.name, name, value, interactive)) {
+            Err(HelperError::NotInstalled) => Err(error.to_string()),
+            result => result.map_err(|e| e.to_string()),
+        }
+    }
+
+    fn apply_with<E>(&self, limit: &ChargeLimit, mut write: impl FnMut(&str, &str) -> Result<(), E>) -> Result<(), E> {
+        let start = limit.start.filter(|s| Some(*s) != self.start);
+        let end = limit.end.filter(|e| Some(*e) != self.end);
+        let mut write_threshold = |name: &str, value: Option<u8>| match value {
+            Some(val) => write(name, &val.to_string()),
+            None => Ok(()),
+        };
+
+        // Drivers reject a start at or above the end, so the end goes first
+        // when it's raised and last when it's lowered.
+        let raising_end = end.is_some_and(|e| self.end.is_none_or(|current| e > current));
+        if raising_end {
+            write_threshold(END_THRESHOLD, end)?;
+            write_threshold(START_THRESHOLD, start)?;
+        } else {
+            write_threshold(START_THRESHOLD, start)?;
+            write_threshold(END_THRESHOLD, end)?;
+        }
+
+        if let Some(behaviour) = limit.behaviour.filter(|b| Some(*b) != self.behaviour) {
+            write(BEHAVIOUR, &behaviour.to_string())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(files: &[(&str, &str)]) -> (tempfile::TempDir, ChargeControl) {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("BAT0")).unwrap();
+        for (name, value) in files {
+            std::fs::write(dir.path().join("BAT0").join(name), format!("{}\n", value)).unwrap();
+        }
+        let control = ChargeControl::load(dir.path(), "BAT0").unwrap();
+
+        (dir, control)
+    }
+
+    fn limit(start: Option<u8>, end: Option<u8>, behaviour: Option<ChargeBehaviour>) -> ChargeLimit {
+        ChargeLimit { battery: None, start, end, behaviour }
+    }
+
+    #[test]
+    fn parses_the_selected_and_available_behaviours() {
+        let (_dir, control) = load(&[(BEHAVIOUR, "auto [inhibit-charge] force-discharge")]);
+        assert_eq!(control.behaviour, Some(ChargeBehaviour::InhibitCharge));
+        assert_eq!(control.available, Some(vec![
+            ChargeBehaviour::Auto,
+            ChargeBehaviour::InhibitCharge,
+            ChargeBehaviour::ForceDischarge,
+        ]));
+        assert_eq!((control.start, control.end), (None, None));
+
+        let (_dir, control) = load(&[(BEHAVIOUR, "auto")]);
+        assert_eq!((control.behaviour, control.available), (Some(ChargeBehaviour::Auto), None));
+    }
+
+    #[test]
+    fn tolerates_unknown_behaviours() {
+        let (_dir, control) = load(&[
+            (END_THRESHOLD, "80"),
+            (BEHAVIOUR, "auto inhibit-charge [inhibit-charge-awake] force-discharge"),
+        ]);
+
+        assert_eq!(control.end, Some(80));
+        assert_eq!(control.behaviour, None);
+        assert_eq!(control.available.as_ref().map(Vec::len), Some(3));
+        assert!(control.validate(&limit(None, Some(70), Some(ChargeBehaviour::Auto))).is_ok());
+        assert!(control.differs(&limit(None, None, Some(ChargeBehaviour::Auto))));
+    }
+
+    #[test]
+    fn validates_against_the_driver() {
+        let (_dir, control) = load(&[
+            (START_THRESHOLD, "40"),
+            (END_THRESHOLD, "80"),
+            (BEHAVIOUR, "[auto] inhibit-charge"),
+        ]);
+
+        assert!(control.validate(&limit(Some(60), Some(90), Some(ChargeBehaviour::InhibitCharge))).is_ok());
+        // Checked against the threshold that stays in place.
+        assert!(control.validate(&limit(Some(85), None, None)).is_err());
+        assert!(control.validate(&limit(None, Some(40), None)).is_err());
+        assert!(control.validate(&limit(None, None, Some(ChargeBehaviour::ForceDischarge))).is_err());
+
+        let (_dir, end_only) = load(&[(END_THRESHOLD, "100")]);
+        assert!(end_only.validate(&limit(None, Some(80), None)).is_ok());
+        assert!(end_only.validate(&limit(Some(40), None, None)).is_err());
+        assert!(end_only.validate(&limit(None, None, Some(ChargeBehaviour::Auto))).is_err());
+    }
+
+    #[test]
+    fn applies_only_what_differs() {
+        let (dir, control) = load(&[(START_THRESHOLD, "40"), (END_THRESHOLD, "80")]);
+        let target = limit(Some(40), Some(60), None);
+        assert!(control.differs(&target));
+        assert!(!control.differs(&limit(Some(40), None, None)));
+
+        control.apply(&target, false).unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.path().join("BAT0").join(name)).unwrap();
+        assert_eq!((read(START_THRESHOLD).as_str(), read(END_THRESHOLD).as_str()), ("40\n", "60"));
+    }
+}
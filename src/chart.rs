@@ -0,0 +1,107 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Terminal charts of percentages over time.
+
+const BLOCKS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Averages timestamped values into `width` columns spread evenly over their
+/// time span. Columns no value falls into, like while the machine was off,
+/// are `None`.
+pub fn columns(points: &[(u64, f64)], width: usize) -> Vec<Option<f64>> {
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => (first.0, last.0),
+        _ => return Vec::new(),
+    };
+    let width = width.min(points.len()).max(1);
+    let span = last.saturating_sub(first).max(1);
+
+    let mut sums = vec![(0.0, 0); width];
+    for (timestamp, value) in points {
+        let column = (timestamp.saturating_sub(first) as u128 * (width as u128 - 1) / span as u128) as usize;
+        sums[column].0 += value;
+        sums[column].1 += 1;
+    }
+
+    sums
+        .into_iter()
+        .map(|(sum, count)| (count > 0).then(|| sum / count as f64))
+        .collect()
+}
+
+/// A single line of bars for percentages, where empty columns are blank.
+pub fn sparkline(columns: &[Option<f64>]) -> String {
+    columns
+        .iter()
+        .map(|value| match value {
+            Some(v) => BLOCKS[1 + (v.clamp(0.0, 100.0) / 100.0 * 7.0).round() as usize],
+            None => ' ',
+        })
+        .collect()
+}
+
+/// A chart of percentages `height` rows high, with a percent axis on the left.
+pub fn chart(columns: &[Option<f64>], height: usize) -> Vec<String> {
+    let step = 100.0 / height as f64;
+
+    (0..height)
+        .rev()
+        .map(|row| {
+            let label = match row {
+                r if r == height - 1 => "100%",
+                0 => "0%",
+                _ => "",
+            };
+            let bars: String = columns
+                .iter()
+                .map(|value| match value {
+                    Some(v) => {
+                        let fill = ((v - row as f64 * step) / step).clamp(0.0, 1.0);
+                        BLOCKS[(fill * 8.0).round() as usize]
+                    }
+                    None => ' ',
+                })
+                .collect();
+
+            format!("{:>4} │{}", label, bars).trim_end().to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_points_into_columns() {
+        let points = [(0, 100.0), (10, 80.0), (50, 60.0), (100, 0.0)];
+
+        assert_eq!(columns(&points, 3), vec![Some(90.0), Some(60.0), Some(0.0)]);
+        // There are never more columns than points.
+        assert_eq!(columns(&points, 11), vec![Some(90.0), Some(60.0), None, Some(0.0)]);
+    }
+
+    #[test]
+    fn draws_bars_by_percentage() {
+        let columns = [Some(100.0), Some(50.0), None, Some(0.0)];
+
+        assert_eq!(sparkline(&columns), "█▅ ▁");
+        assert_eq!(chart(&columns, 2), vec!["100% │█", "  0% │██"]);
+    }
+}
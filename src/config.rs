@@ -18,9 +18,12 @@
 // THE SOFTWARE.
 
 use std::str::FromStr;
-use std::{fs, path::PathBuf};
+use std::{fs, path::PathBuf, time::Duration};
 use std::env;
 use serde::{Serialize, Deserialize};
+use crate::charge::ChargeBehaviour;
+use crate::notify::Urgency;
+use crate::power::Action;
 use crate::presets;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -28,15 +31,329 @@ pub struct Config {
     pub batteries: Vec<String>,
     pub read_delay_ms: u64,
 
-    pub good_capacity: u8,
-    pub okay_capacity: u8,
-    pub bad_capacity: u8,
-    pub critical_capacity: u8,
+    /// How far past a threshold the capacity has to move back before it can fire again.
+    #[serde(default = "default_hysteresis")]
+    pub hysteresis: u8,
 
-    pub good_capacity_handler: Option<String>,
-    pub okay_capacity_handler: Option<String>,
-    pub bad_capacity_handler: Option<String>,
-    pub critical_capacity_handler: Option<String>,
+    pub ac_connected_handler: Option<Handler>,
+    pub ac_disconnected_handler: Option<Handler>,
+    pub charging_started_handler: Option<Handler>,
+    pub discharging_started_handler: Option<Handler>,
+    pub full_handler: Option<Handler>,
+
+    /// How long to wait before a threshold's action, giving time to plug in the charger.
+    #[serde(default = "default_action_countdown")]
+    pub action_countdown_secs: u64,
+
+    #[serde(default)]
+    pub notifications: Notifications,
+
+    #[serde(default)]
+    pub history: History,
+
+    #[serde(default)]
+    pub health: Health,
+
+    #[serde(default, rename = "charge_limit")]
+    pub charge_limits: Vec<ChargeLimit>,
+
+    #[serde(default, rename = "threshold")]
+    pub thresholds: Vec<Threshold>,
+}
+
+/// The defaults of the desktop notifications thresholds can show.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Notifications {
+    pub app_name: String,
+    pub summary: String,
+    pub body: String,
+    /// How long notifications stay up in milliseconds, `-1` for the server's default.
+    pub timeout_ms: i32,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            app_name: "batteryctl".to_string(),
+            summary: "Battery {event}".to_string(),
+            body: "{device} is at {capacity}%".to_string(),
+            timeout_ms: -1,
+        }
+    }
+}
+
+/// Where the daemon records the samples it reads, under `$XDG_STATE_HOME/batteryctl`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct History {
+    pub enabled: bool,
+    /// The size at which the history file is rotated, in KiB.
+    pub max_size_kb: u64,
+    /// How many rotated files to keep.
+    pub max_files: u32,
+    /// The age at which the history file is rotated and rotated files are deleted.
+    pub max_age_days: u64,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_size_kb: 1024,
+            max_files: 5,
+            max_age_days: 180,
+        }
+    }
+}
+
+/// When the daemon reports a battery as worn out, from the full capacity it records daily.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Health {
+    /// The full capacity in percent of the design capacity below which the battery should be replaced.
+    pub replacement_percent: u8,
+    pub handler: Option<Handler>,
+    pub notify: Option<Notify>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            replacement_percent: 70,
+            handler: None,
+            notify: None,
+        }
+    }
+}
+
+/// The charge thresholds and behaviour the daemon keeps applied, since some
+/// firmware resets them on resume or reboot.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChargeLimit {
+    /// Only apply to this battery instead of all of them.
+    pub battery: Option<String>,
+    /// Charging starts again once the capacity drops below this percentage.
+    pub start: Option<u8>,
+    /// Charging stops at this percentage.
+    pub end: Option<u8>,
+    pub behaviour: Option<ChargeBehaviour>,
+}
+
+impl ChargeLimit {
+    pub fn applies_to(&self, battery: &str) -> bool {
+        self.battery.as_ref().is_none_or(|b| b == battery)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start.is_none() && self.end.is_none() && self.behaviour.is_none()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [("start", self.start), ("end", self.end)] {
+            if value.is_some_and(|v| v > 100) {
+                return Err(format!("The charge {} threshold has to be a percentage from 0 to 100", name));
+            }
+        }
+        if let (Some(start), Some(end)) = (self.start, self.end) {
+            if start >= end {
+                return Err(format!("The charge start threshold ({}%) has to be below the end threshold ({}%)", start, end));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a threshold shows a desktop notification: `notify = true`, or a
+/// table overriding the defaults from `[notifications]`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Notify {
+    Enabled(bool),
+    Options(NotifyOptions),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct NotifyOptions {
+    pub summary: Option<String>,
+    pub body: Option<String>,
+    pub urgency: Option<Urgency>,
+    /// An icon name; picked from the charge level by default.
+    pub icon: Option<String>,
+}
+
+impl Notify {
+    /// The options of an enabled notification, or `None` if it's disabled.
+    pub fn options(&self) -> Option<NotifyOptions> {
+        match self {
+            Notify::Enabled(true) => Some(NotifyOptions::default()),
+            Notify::Enabled(false) => None,
+            Notify::Options(options) => Some(options.clone()),
+        }
+    }
+}
+
+/// A capacity percentage or estimated time left the daemon reacts to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Threshold {
+    pub name: String,
+    pub percentage: Option<u8>,
+    /// Fire when fewer minutes than this are left until the battery is empty,
+    /// or full for rising thresholds, instead of at a percentage.
+    pub minutes: Option<u32>,
+    #[serde(default)]
+    pub direction: Direction,
+    /// Only watch this battery instead of all of them.
+    pub battery: Option<String>,
+    pub handler: Option<Handler>,
+    pub notify: Option<Notify>,
+    /// Suspends, hibernates or powers off the system after the countdown.
+    pub action: Option<Action>,
+    #[serde(default)]
+    pub repeat: Repeat,
+}
+
+impl Threshold {
+    pub fn new(name: &str, percentage: u8, direction: Direction) -> Self {
+        Self {
+            name: name.to_string(),
+            percentage: Some(percentage),
+            minutes: None,
+            direction,
+            battery: None,
+            handler: None,
+            notify: None,
+            action: None,
+            repeat: Repeat::default(),
+        }
+    }
+
+    pub fn applies_to(&self, battery: &str) -> bool {
+        self.battery.as_ref().is_none_or(|b| b == battery)
+    }
+}
+
+/// A command the daemon runs when an event fires.
+///
+/// A plain string is a shell command line, kept for older configs. An array
+/// is a program and its arguments, run directly without a shell.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Handler {
+    Shell(String),
+    Command(Vec<String>),
+    Detailed(HandlerOptions),
+}
+
+/// A handler with options, e.g. `{ command = ["notify-send", "Low"], timeout = 5 }`.
+/// Exactly one of `command` and `shell` has to be set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HandlerOptions {
+    pub command: Option<Vec<String>>,
+    pub shell: Option<String>,
+    /// Seconds to wait before killing the handler.
+    pub timeout: Option<u64>,
+}
+
+/// How long a handler may run before it's killed, unless it sets its own timeout.
+pub const DEFAULT_HANDLER_TIMEOUT_SECS: u64 = 60;
+
+impl Handler {
+    pub fn timeout(&self) -> Duration {
+        let secs = match self {
+            Handler::Detailed(options) => options.timeout.unwrap_or(DEFAULT_HANDLER_TIMEOUT_SECS),
+            _ => DEFAULT_HANDLER_TIMEOUT_SECS,
+        };
+
+        Duration::from_secs(secs)
+    }
+
+    /// The shell command line, if the handler runs in a shell.
+    pub fn shell(&self) -> Option<&str> {
+        match self {
+            Handler::Shell(line) => Some(line),
+            Handler::Detailed(HandlerOptions { shell: Some(line), .. }) => Some(line),
+            _ => None,
+        }
+    }
+
+    /// The program and arguments, if the handler runs without a shell.
+    pub fn command(&self) -> Option<&[String]> {
+        match self {
+            Handler::Command(argv) => Some(argv),
+            Handler::Detailed(HandlerOptions { command: Some(argv), .. }) => Some(argv),
+            _ => None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Handler::Shell(line) if line.trim().is_empty() => Err("The handler is empty".to_string()),
+            Handler::Command(argv) if argv.is_empty() => Err("The handler command is empty".to_string()),
+            Handler::Detailed(options) => match (&options.command, &options.shell) {
+                (Some(_), Some(_)) => Err("A handler can't have both a command and a shell command line".to_string()),
+                (None, None) => Err("A handler needs either a command or a shell command line".to_string()),
+                (Some(argv), None) if argv.is_empty() => Err("The handler command is empty".to_string()),
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Falling thresholds fire while discharging, rising thresholds while charging.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    #[default]
+    Falling,
+    Rising,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Repeat {
+    /// Fire once per crossing, re-arming after moving back past the hysteresis margin.
+    #[default]
+    Crossing,
+    /// Fire only the first time, until the daemon restarts.
+    Once,
+    /// Fire on every read while past the threshold.
+    EveryRead,
+}
+
+/// The fixed levels of older configs, before `[[threshold]]`.
+#[derive(Deserialize, Debug, Default)]
+struct LegacyConfig {
+    good_capacity: Option<u8>,
+    okay_capacity: Option<u8>,
+    bad_capacity: Option<u8>,
+    critical_capacity: Option<u8>,
+
+    good_capacity_handler: Option<String>,
+    okay_capacity_handler: Option<String>,
+    bad_capacity_handler: Option<String>,
+    critical_capacity_handler: Option<String>,
+}
+
+impl LegacyConfig {
+    fn into_thresholds(self) -> Vec<Threshold> {
+        let levels = [
+            ("good", self.good_capacity, self.good_capacity_handler),
+            ("okay", self.okay_capacity, self.okay_capacity_handler),
+            ("bad", self.bad_capacity, self.bad_capacity_handler),
+            ("critical", self.critical_capacity, self.critical_capacity_handler),
+        ];
+
+        levels
+            .into_iter()
+            .filter_map(|(name, percentage, handler)| percentage.map(|percentage| Threshold {
+                handler: handler.map(Handler::Shell),
+                ..Threshold::new(name, percentage, Direction::Falling)
+            }))
+            .collect()
+    }
 }
 
 impl Config {
@@ -44,16 +361,112 @@ impl Config {
         Self {
             batteries: Vec::new(),
             read_delay_ms: 180000,
-            good_capacity: 75,
-            okay_capacity: 50,
-            bad_capacity: 20,
-            critical_capacity: 5,
-            good_capacity_handler: None,
-            okay_capacity_handler: None,
-            bad_capacity_handler: None,
-            critical_capacity_handler: None,
+            hysteresis: default_hysteresis(),
+            ac_connected_handler: None,
+            ac_disconnected_handler: None,
+            charging_started_handler: None,
+            discharging_started_handler: None,
+            full_handler: None,
+            action_countdown_secs: default_action_countdown(),
+            notifications: Notifications::default(),
+            history: History::default(),
+            health: Health::default(),
+            charge_limits: Vec::new(),
+            thresholds: vec![
+                Threshold::new("good", 75, Direction::Falling),
+                Threshold::new("okay", 50, Direction::Falling),
+                Threshold {
+                    notify: Some(Notify::Enabled(true)),
+                    ..Threshold::new("bad", 20, Direction::Falling)
+                },
+                Threshold {
+                    notify: Some(Notify::Options(NotifyOptions {
+                        urgency: Some(Urgency::Critical),
+                        ..NotifyOptions::default()
+                    })),
+                    ..Threshold::new("critical", 5, Direction::Falling)
+                },
+            ],
+        }
+    }
+
+    /// Parses a config, turning the keys of older configs into `[[threshold]]` entries.
+    pub fn parse(contents: &str) -> Result<Self, toml::de::Error> {
+        let mut config = toml::de::from_str::<Self>(contents)?;
+        let legacy = toml::de::from_str::<LegacyConfig>(contents)?;
+
+        config.thresholds.extend(legacy.into_thresholds());
+
+        Ok(config)
+    }
+
+    /// Checks the parts of the config serde can't, like handlers having exactly one command.
+    pub fn validate(&self) -> Result<(), String> {
+        let event_handlers = [
+            ("ac_connected_handler", &self.ac_connected_handler),
+            ("ac_disconnected_handler", &self.ac_disconnected_handler),
+            ("charging_started_handler", &self.charging_started_handler),
+            ("discharging_started_handler", &self.discharging_started_handler),
+            ("full_handler", &self.full_handler),
+            ("health.handler", &self.health.handler),
+        ];
+        for (key, handler) in event_handlers {
+            if let Some(handler) = handler {
+                handler.validate().map_err(|e| format!("{key}: {e}"))?;
+            }
         }
+
+        for limit in &self.charge_limits {
+            limit.validate().map_err(|e| format!("charge_limit: {e}"))?;
+        }
+
+        for threshold in &self.thresholds {
+            if threshold.percentage.is_some() == threshold.minutes.is_some() {
+                return Err(format!("threshold '{}': Set either a percentage or minutes", threshold.name));
+            }
+            if let Some(handler) = &threshold.handler {
+                handler.validate().map_err(|e| format!("threshold '{}': {e}", threshold.name))?;
+            }
+        }
+
+        Ok(())
     }
+
+    /// The falling threshold a capacity currently sits under, i.e. the one with
+    /// the lowest percentage that's still at or above the capacity.
+    pub fn level(&self, battery: &str, capacity: u8) -> Option<&Threshold> {
+        self.thresholds
+            .iter()
+            .filter(|t| t.direction == Direction::Falling && t.applies_to(battery))
+            .filter(|t| t.percentage.is_some_and(|p| capacity <= p))
+            .min_by_key(|t| t.percentage)
+    }
+
+    /// The charge limit of a battery, with the `[[charge_limit]]` entries that
+    /// apply to it merged in order.
+    pub fn charge_limit(&self, battery: &str) -> Option<ChargeLimit> {
+        self.charge_limits
+            .iter()
+            .filter(|l| l.applies_to(battery))
+            .cloned()
+            .reduce(|merged, limit| ChargeLimit {
+                battery: limit.battery.or(merged.battery),
+                start: limit.start.or(merged.start),
+                end: limit.end.or(merged.end),
+                behaviour: limit.behaviour.or(merged.behaviour),
+            })
+    }
+
+    /// Formats the thresholds as the `[[threshold]]` entries of a config file.
+    pub fn thresholds_toml(&self) -> String {
+        #[derive(Serialize)]
+        struct Thresholds<'a> {
+            threshold: &'a Vec<Threshold>,
+        }
+
+        toml::to_string_pretty(&Thresholds { threshold: &self.thresholds }).unwrap()
+    }
+
     pub fn save(&self) {
         let contents = toml::to_string_pretty(self).unwrap();
 
@@ -62,28 +475,42 @@ impl Config {
         fs::write(path, contents).unwrap();
     }
 
-    pub fn load() -> std::io::Result<Self> {
-        let path = get_config_path()?;
+    /// Loads the config, writing the preset first if there is none. A config
+    /// that exists is never overwritten, even if it doesn't parse, since it
+    /// may hold hand-written thresholds.
+    pub fn load() -> Result<Self, String> {
+        let path = get_config_path().map_err(|e| format!("Can't create the config directory: {}", e))?;
 
-        let contents;
-        match fs::read_to_string(&path) {
-            Ok(val) => contents = val,
-            Err(_) => {
-                fs::write(&path, presets::CONFIG)?;
-                return Ok(Self::new())
+        let contents = match fs::read_to_string(&path) {
+            Ok(val) => val,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // Created only if it still doesn't exist, in case another instance just wrote one.
+                let created = fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)
+                    .and_then(|mut file| std::io::Write::write_all(&mut file, presets::CONFIG.as_bytes()));
+                return match created {
+                    Ok(_) => Ok(Self::new()),
+                    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Self::load(),
+                    Err(e) => Err(format!("Can't write the default config to {}: {}", path.display(), e)),
+                };
             }
-        }
+            Err(e) => return Err(format!("Can't read {}: {}", path.display(), e)),
+        };
 
-        match toml::de::from_str::<Self>(&contents) {
-            Ok(val) => Ok(val),
-            Err(_) => {
-                fs::write(&path, presets::CONFIG)?;
-                return Ok(Self::new())
-            }
-        }
+        Self::parse(&contents).map_err(|e| format!("Invalid config {}: {}", path.display(), e))
     }
 }
 
+fn default_hysteresis() -> u8 {
+    2
+}
+
+fn default_action_countdown() -> u64 {
+    60
+}
+
 fn get_config_path() -> std::io::Result<PathBuf> {
     let xdg_home = env::var("XDG_CONFIG_HOME")
         .or_else(|_| env::var("HOME")
@@ -103,3 +530,203 @@ fn get_config_path() -> std::io::Result<PathBuf> {
     Ok(path)
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_fixed_levels() {
+        let config = Config::parse(concat!(
+            "batteries = [\"BAT0\"]\n",
+            "read_delay_ms = 180000\n",
+            "good_capacity = 75\n",
+            "okay_capacity = 50\n",
+            "bad_capacity = 20\n",
+            "critical_capacity = 5\n",
+            "critical_capacity_handler = \"systemctl hibernate\"\n",
+        )).unwrap();
+
+        assert_eq!(config.thresholds, vec![
+            Threshold::new("good", 75, Direction::Falling),
+            Threshold::new("okay", 50, Direction::Falling),
+            Threshold::new("bad", 20, Direction::Falling),
+            Threshold {
+                handler: Some(Handler::Shell("systemctl hibernate".to_string())),
+                ..Threshold::new("critical", 5, Direction::Falling)
+            },
+        ]);
+    }
+
+    #[test]
+    fn parses_thresholds() {
+        let config = Config::parse(concat!(
+            "batteries = []\n",
+            "read_delay_ms = 180000\n",
+            "\n",
+            "[[threshold]]\n",
+            "name = \"unplug\"\n",
+            "percentage = 80\n",
+            "direction = \"rising\"\n",
+            "battery = \"BAT1\"\n",
+            "handler = \"notify-send unplug\"\n",
+            "repeat = \"every-read\"\n",
+        )).unwrap();
+
+        assert_eq!(config.thresholds, vec![Threshold {
+            name: "unplug".to_string(),
+            percentage: Some(80),
+            minutes: None,
+            direction: Direction::Rising,
+            battery: Some("BAT1".to_string()),
+            handler: Some(Handler::Shell("notify-send unplug".to_string())),
+            notify: None,
+            action: None,
+            repeat: Repeat::EveryRead,
+        }]);
+    }
+
+    #[test]
+    fn parses_actions() {
+        let config = Config::parse(concat!(
+            "batteries = []\n",
+            "read_delay_ms = 180000\n",
+            "action_countdown_secs = 30\n",
+            "\n",
+            "[[threshold]]\n",
+            "name = \"critical\"\n",
+            "percentage = 5\n",
+            "action = \"hybrid-sleep\"\n",
+        )).unwrap();
+
+        assert_eq!(config.action_countdown_secs, 30);
+        assert!(config.validate().is_ok());
+        assert_eq!(config.thresholds[0].action, Some(Action::HybridSleep));
+        assert!(Config::parse("batteries = []\nread_delay_ms = 1\n[[threshold]]\nname = \"a\"\npercentage = 5\naction = \"reboot\"\n").is_err());
+    }
+
+    #[test]
+    fn parses_every_handler_form() {
+        let config = Config::parse(concat!(
+            "batteries = []\n",
+            "read_delay_ms = 180000\n",
+            "ac_connected_handler = \"notify-send 'AC connected'\"\n",
+            "ac_disconnected_handler = [\"~/bin/notify\", \"AC disconnected\"]\n",
+            "full_handler = { shell = \"logger full\", timeout = 5 }\n",
+            "charging_started_handler = { command = [\"logger\", \"charging\"] }\n",
+        )).unwrap();
+
+        assert_eq!(config.ac_connected_handler.unwrap().shell(), Some("notify-send 'AC connected'"));
+        let disconnected = config.ac_disconnected_handler.unwrap();
+        assert_eq!(disconnected.command().unwrap(), ["~/bin/notify", "AC disconnected"]);
+        assert_eq!(disconnected.timeout(), Duration::from_secs(DEFAULT_HANDLER_TIMEOUT_SECS));
+        let full = config.full_handler.unwrap();
+        assert_eq!(full.shell(), Some("logger full"));
+        assert_eq!(full.timeout(), Duration::from_secs(5));
+        assert_eq!(config.charging_started_handler.unwrap().command().unwrap(), ["logger", "charging"]);
+    }
+
+    #[test]
+    fn rejects_ambiguous_handlers() {
+        let handler = Handler::Detailed(HandlerOptions {
+            command: Some(vec!["true".to_string()]),
+            shell: Some("true".to_string()),
+            timeout: None,
+        });
+
+        assert!(handler.validate().is_err());
+        assert!(Handler::Command(Vec::new()).validate().is_err());
+        assert!(Handler::Shell("true".to_string()).validate().is_ok());
+    }
+
+    #[test]
+    fn thresholds_need_either_a_percentage_or_minutes() {
+        let parse = |threshold: &str| Config::parse(&format!(
+            "batteries = []\nread_delay_ms = 1\n[[threshold]]\nname = \"low\"\n{}",
+            threshold,
+        )).unwrap();
+
+        let config = parse("minutes = 15\n");
+        assert_eq!((config.thresholds[0].percentage, config.thresholds[0].minutes), (None, Some(15)));
+        assert!(config.validate().is_ok());
+        assert!(parse("percentage = 5\nminutes = 15\n").validate().is_err());
+        assert!(parse("direction = \"rising\"\n").validate().is_err());
+    }
+
+    #[test]
+    fn parses_charge_limits() {
+        let parse = |limit: &str| Config::parse(&format!(
+            "batteries = []\nread_delay_ms = 1\n[[charge_limit]]\n{}",
+            limit,
+        )).unwrap();
+
+        let config = parse("battery = \"BAT0\"\nstart = 75\nend = 80\nbehaviour = \"inhibit-charge\"\n");
+        assert_eq!(config.charge_limits, vec![ChargeLimit {
+            battery: Some("BAT0".to_string()),
+            start: Some(75),
+            end: Some(80),
+            behaviour: Some(ChargeBehaviour::InhibitCharge),
+        }]);
+        assert!(config.validate().is_ok());
+        assert_eq!(config.charge_limit("BAT1"), None);
+        assert!(parse("start = 80\nend = 80\n").validate().is_err());
+        assert!(parse("end = 120\n").validate().is_err());
+    }
+
+    #[test]
+    fn preset_matches_the_defaults() {
+        let preset = Config::parse(presets::CONFIG).unwrap();
+
+        assert_eq!(preset.thresholds, Config::new().thresholds);
+    }
+
+    #[test]
+    fn parses_notifications() {
+        let config = Config::parse(concat!(
+            "batteries = []\n",
+            "read_delay_ms = 180000\n",
+            "\n",
+            "[notifications]\n",
+            "summary = \"{device} is low\"\n",
+            "\n",
+            "[[threshold]]\n",
+            "name = \"bad\"\n",
+            "percentage = 20\n",
+            "notify = true\n",
+            "\n",
+            "[[threshold]]\n",
+            "name = \"critical\"\n",
+            "percentage = 5\n",
+            "notify = { urgency = \"critical\", body = \"Plug me in!\" }\n",
+        )).unwrap();
+
+        assert_eq!(config.notifications.summary, "{device} is low");
+        assert_eq!(config.notifications.body, Notifications::default().body);
+        assert_eq!(config.thresholds[0].notify.as_ref().unwrap().options(), Some(NotifyOptions::default()));
+        assert_eq!(config.thresholds[1].notify.as_ref().unwrap().options(), Some(NotifyOptions {
+            body: Some("Plug me in!".to_string()),
+            urgency: Some(Urgency::Critical),
+            ..NotifyOptions::default()
+        }));
+    }
+
+    #[test]
+    fn saved_configs_round_trip() {
+        let mut config = Config::new();
+        config.full_handler = Some(Handler::Detailed(HandlerOptions {
+            command: Some(vec!["logger".to_string(), "full".to_string()]),
+            shell: None,
+            timeout: Some(5),
+        }));
+        config.thresholds[0].notify = Some(Notify::Options(NotifyOptions {
+            icon: Some("battery-good".to_string()),
+            ..NotifyOptions::default()
+        }));
+
+        let parsed = Config::parse(&toml::to_string_pretty(&config).unwrap()).unwrap();
+
+        assert_eq!(parsed.full_handler, config.full_handler);
+        assert_eq!(parsed.notifications, config.notifications);
+        assert_eq!(parsed.thresholds, config.thresholds);
+    }
+}
@@ -17,14 +17,26 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use std::{collections::HashMap, fmt, process::Command, thread::sleep, time::Duration};
+use std::{collections::HashMap, fmt, path::Path, time::{Duration, Instant}};
 
 use crate::battery;
+use crate::charge;
 use crate::config;
+use crate::handler;
+use crate::health;
+use crate::history;
+use crate::netlink;
+use crate::notify;
+use crate::power;
+
+/// How long the daemon waits before applying a charge limit again after it failed.
+const CHARGE_LIMIT_RETRY: Duration = Duration::from_secs(10 * 60);
 
 #[derive(Debug)]
 pub enum DaemonError {
     IO(String),
+    Battery(battery::BatteryError),
+    Config(String),
 }
 
 impl std::error::Error for DaemonError { }
@@ -33,130 +45,913 @@ impl fmt::Display for DaemonError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::IO(e) => write!(f, "IO error: {}", e),
+            Self::Battery(e) => write!(f, "Battery error: {}", e),
+            Self::Config(e) => write!(f, "Config error: {}", e),
+        }
+    }
+}
+
+impl From<battery::BatteryError> for DaemonError {
+    fn from(e: battery::BatteryError) -> Self {
+        match e {
+            battery::BatteryError::IOError(s) => Self::IO(s),
+            e => Self::Battery(e),
         }
     }
 }
 
+/// Something the daemon reacts to by running a handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The capacity crossed the threshold with this index in the config.
+    Threshold(usize),
+    ChargingStarted,
+    DischargingStarted,
+    Full,
+}
+
+/// Tracks which thresholds of a battery have already fired.
+///
+/// A falling threshold fires once when the capacity drops to or below its
+/// percentage and re-arms only once the capacity climbs above the percentage
+/// plus the hysteresis margin, so a battery hovering around a threshold
+/// doesn't trigger the handler on every read. Rising thresholds work the same
+/// way in the opposite direction. Time thresholds fire when the estimated
+/// time left drops to their minutes and re-arm once it's more than the
+/// hysteresis in minutes above them.
+#[derive(Debug, Default)]
 struct BatteryStatus {
-    pub good_handled: bool,
-    pub okay_handled: bool,
-    pub bad_handled: bool,
-    pub critical_handled: bool,
+    pub handled: Vec<bool>,
+    pub last_status: Option<battery::Status>,
+    /// The name of the falling threshold the capacity sits under.
+    pub level: Option<String>,
+    /// The level before the last level change.
+    pub previous_level: Option<String>,
+    /// The id of the last notification shown for the battery, which the next one replaces.
+    pub notification_id: u32,
+    pub estimator: battery::Estimator,
+    /// The time left at the last reading.
+    pub time_left: battery::TimeLeft,
+    /// Why the charge limit couldn't be applied at the last reading, logged only when it changes.
+    pub charge_limit_error: Option<String>,
+    /// When to try applying the charge limit again after it failed.
+    pub charge_limit_retry: Option<Instant>,
+}
+
+impl BatteryStatus {
+    /// Feeds a reading into the state machine, returning the events to fire.
+    fn update(
+        &mut self,
+        config: &config::Config,
+        bat: &str,
+        capacity: u8,
+        status: Option<battery::Status>,
+        time_left: battery::TimeLeft,
+    ) -> Vec<Event> {
+        let mut events = Vec::new();
+        self.time_left = time_left;
+
+        let level = config.level(bat, capacity).map(|t| t.name.clone());
+        if level != self.level {
+            self.previous_level = std::mem::replace(&mut self.level, level);
+        }
+
+        events.extend(self.update_status(status));
+        // The low battery handlers are pointless while the charger is connected.
+        let discharging = !matches!(
+            status,
+            Some(battery::Status::Charging | battery::Status::Full | battery::Status::NotCharging)
+        );
+        let charging = matches!(status, Some(battery::Status::Charging | battery::Status::Full));
+        events.extend(self
+            .update_thresholds(config, bat, capacity, time_left, discharging, charging)
+            .into_iter()
+            .map(Event::Threshold));
+
+        events
+    }
+
+    /// Reports changes of the charging status. The first reading only sets the baseline.
+    fn update_status(&mut self, status: Option<battery::Status>) -> Option<Event> {
+        let previous = self.last_status;
+        self.last_status = status;
+
+        if previous.is_none() || previous == status {
+            return None;
+        }
+        match status? {
+            battery::Status::Charging => Some(Event::ChargingStarted),
+            battery::Status::Discharging => Some(Event::DischargingStarted),
+            battery::Status::Full => Some(Event::Full),
+            _ => None,
+        }
+    }
+
+    /// Returns the indices of the thresholds to fire. Falling thresholds only
+    /// fire while `discharging` and rising ones while `charging`. When a single
+    /// reading crosses several thresholds of one direction at once, only the
+    /// most extreme ones fire and the others are marked as handled.
+    fn update_thresholds(
+        &mut self,
+        config: &config::Config,
+        bat: &str,
+        capacity: u8,
+        time_left: battery::TimeLeft,
+        discharging: bool,
+        charging: bool,
+    ) -> Vec<usize> {
+        self.handled.resize(config.thresholds.len(), false);
+
+        let mut fired = Vec::new();
+        for (direction, active) in [(config::Direction::Falling, discharging), (config::Direction::Rising, charging)] {
+            let thresholds = config.thresholds
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| t.direction == direction && t.applies_to(bat));
+
+            let mut crossed = Vec::new();
+            for (i, threshold) in thresholds {
+                let (past, rearm) = position(threshold, capacity, time_left, config.hysteresis);
+
+                if rearm && threshold.repeat != config::Repeat::Once {
+                    self.handled[i] = false;
+                }
+                if !active || !past {
+                    continue;
+                }
+
+                if !self.handled[i] || threshold.repeat == config::Repeat::EveryRead {
+                    crossed.push(i);
+                }
+                self.handled[i] = true;
+            }
+
+            // Time and capacity thresholds are compared separately, since
+            // minutes and percent say nothing about each other.
+            let severity = |i: &usize| {
+                let threshold = &config.thresholds[*i];
+                match (threshold.minutes, threshold.percentage, direction) {
+                    (Some(minutes), _, _) => (true, minutes as i32),
+                    (None, percentage, config::Direction::Falling) => (false, percentage.unwrap_or(0) as i32),
+                    (None, percentage, config::Direction::Rising) => (false, -(percentage.unwrap_or(0) as i32)),
+                }
+            };
+            fired.extend(crossed
+                .iter()
+                .filter(|i| crossed
+                    .iter()
+                    .all(|j| severity(i).0 != severity(j).0 || severity(i).1 <= severity(j).1))
+                .copied()
+                .collect::<Vec<usize>>());
+        }
+
+        fired
+    }
+}
+
+/// An action waiting for its countdown to pass. Connecting power cancels it.
+#[derive(Debug)]
+struct Countdown {
+    action: power::Action,
+    deadline: Instant,
+    /// The battery and the index of the threshold that started the countdown,
+    /// re-armed when it's cancelled.
+    battery: String,
+    threshold: usize,
+}
+
+/// Whether a reading is past a threshold, and whether it's far enough back to re-arm it.
+fn position(threshold: &config::Threshold, capacity: u8, time_left: battery::TimeLeft, hysteresis: u8) -> (bool, bool) {
+    if let Some(minutes) = threshold.minutes {
+        let remaining = match threshold.direction {
+            config::Direction::Falling => time_left.to_empty,
+            config::Direction::Rising => time_left.to_full,
+        };
+        let minutes_left = match remaining {
+            Some(val) => val.as_secs() / 60,
+            None => return (false, false),
+        };
+        return (minutes_left < minutes as u64, minutes_left >= minutes as u64 + hysteresis as u64);
+    }
+
+    let percentage = threshold.percentage.unwrap_or(0);
+    match threshold.direction {
+        config::Direction::Falling => (
+            capacity <= percentage,
+            capacity > percentage.saturating_add(hysteresis),
+        ),
+        config::Direction::Rising => (
+            capacity >= percentage,
+            capacity < percentage.saturating_sub(hysteresis),
+        ),
+    }
 }
 
 struct DaemonStatus {
     pub batteries: HashMap<String, BatteryStatus>,
+    /// Whether each mains or USB adapter was online at the last read.
+    pub adapters: HashMap<String, bool>,
+    /// Connected on the first notification, since the session bus may not be up yet at start.
+    pub notifier: Option<notify::Notifier>,
+    pub countdown: Option<Countdown>,
+    pub history: Option<history::History>,
+    pub capacity_log: Option<health::CapacityLog>,
 }
 
 impl DaemonStatus {
     pub fn new() -> DaemonStatus {
         Self {
-            batteries: HashMap::new()
+            batteries: HashMap::new(),
+            adapters: HashMap::new(),
+            notifier: None,
+            countdown: None,
+            history: None,
+            capacity_log: None,
         }
     }
+
+    /// Whether an adapter is online or a battery is charging at the last read.
+    fn on_ac(&self) -> bool {
+        self.adapters.values().any(|online| *online)
+            || self.batteries.values().any(|bat| bat.last_status == Some(battery::Status::Charging))
+    }
 }
 
-pub fn start(config: &config::Config) -> Result<(), DaemonError> {
+pub fn start(config: &config::Config, root: &Path) -> Result<(), DaemonError> {
     println!("[WARN] Beta feature");
     // println!("[INFO] Starting batteryctl daemon with config {:?}", config);
 
+    config.validate().map_err(DaemonError::Config)?;
+
     let mut status = DaemonStatus::new();
+    if config.history.enabled {
+        status.history = Some(history::History::open(config.history.clone()));
+    }
+    // The daily capacity records are tiny, and `health --trend` and the
+    // replacement event need them whether or not the samples are kept.
+    status.capacity_log = Some(health::CapacityLog::open());
 
-    let mut capacity_buf: u8;
+    // Without uevents the read delay is the only way to notice changes.
+    let listener = match netlink::Listener::open() {
+        Ok(val) => Some(val),
+        Err(e) => {
+            eprintln!("[WARN] Can't listen for uevents, falling back to polling: {e}");
+            None
+        }
+    };
+
+    // A misspelled battery fails right away. Once running, read errors, like
+    // those of a swapped battery, only skip the battery until the next read.
+    for bat in &config.batteries {
+        battery::Properties::load(root, bat)?;
+    }
 
     loop {
+        if let Err(e) = handle_adapters(config, root, &mut status) {
+            eprintln!("[WARN] Reading the adapters failed: {e}");
+        }
+
+        let mut samples = Vec::new();
         for bat in &config.batteries {
-            match battery::get_device_property_raw(bat, "capacity") {
-                Ok(val) => capacity_buf = val.parse::<u8>().unwrap(),
-                Err(e) => return Err(DaemonError::IO(e.to_string())),
+            handle_charge_limit(config, root, bat, &mut status);
+
+            let (device, capacity) = match battery::Battery::load(root, bat)
+                .and_then(|device| device.require_capacity().map(|capacity| (device, capacity)))
+            {
+                Ok(val) => val,
+                Err(e) => {
+                    eprintln!("[WARN] Skipping {bat} until the next read: {e}");
+                    continue;
+                }
+            };
+            samples.push(history::Sample::of(&device, history::now()));
+
+            match handle_battery(config, &device, capacity, &mut status) {
+                Ok(_) => println!("[INFO] Capacity handled for {}.", device.name),
+                Err(e) => eprintln!("[WARN] Handling {bat} failed: {e}"),
             }
+            handle_capacity_record(config, &device, &mut status);
+        }
 
-            match handle_capacity(config, &capacity_buf, &mut status, bat) {
-                Ok(_) => println!("[INFO] Capacity handled for {bat}."),
-                Err(e) => return Err(e),
+        if let Some(history) = &status.history {
+            if let Err(e) = history.append(&samples) {
+                eprintln!("[ERROR] Recording the history failed: {e}");
             }
         }
+        handle_countdown(&mut status);
 
-        sleep(Duration::from_millis(config.read_delay_ms));
+        // Wake up in time for a running countdown.
+        let heartbeat = Duration::from_millis(config.read_delay_ms);
+        let delay = match &status.countdown {
+            Some(countdown) => heartbeat.min(countdown.deadline.saturating_duration_since(Instant::now())),
+            None => heartbeat,
+        };
+        wait_for_change(listener.as_ref(), delay, |event| is_relevant(event, config, &status));
     }
 }
 
-// TODO: Set the handled variables
-fn handle_capacity(config: &config::Config, capacity: &u8, status: &mut DaemonStatus, bat: &String) -> Result<(), DaemonError> {
-    if !status.batteries[bat].critical_handled && *capacity <= config.critical_capacity {
-        handle_critical(config, bat);
+/// Blocks until a power_supply uevent arrives or the heartbeat delay passes,
+/// whichever comes first. Not every driver emits events on capacity changes,
+/// so the heartbeat is kept even when uevents are available.
+fn wait_for_change(
+    listener: Option<&netlink::Listener>,
+    heartbeat: Duration,
+    wanted: impl Fn(&netlink::Uevent) -> bool,
+) {
+    if let Some(event) = netlink::wait_for_power_supply(listener, heartbeat, wanted) {
+        println!(
+            "[INFO] Received a '{}' uevent for {}.",
+            event.action,
+            event.device_name().unwrap_or("an unknown device"),
+        );
     }
-    else if !status.batteries[bat].bad_handled && *capacity <= config.bad_capacity {
-        handle_bad(config, bat);
+}
+
+/// Whether a uevent is about a configured battery or an adapter, the devices
+/// the daemon reacts to. Peripherals like mice report often and are ignored.
+fn is_relevant(event: &netlink::Uevent, config: &config::Config, status: &DaemonStatus) -> bool {
+    let Some(name) = event.device_name() else {
+        return false;
+    };
+    let is_adapter = || event
+        .properties
+        .get("POWER_SUPPLY_TYPE")
+        .and_then(|kind| kind.parse().ok())
+        .is_some_and(|kind| matches!(kind, battery::DeviceType::Mains | battery::DeviceType::Usb));
+
+    config.batteries.iter().any(|bat| bat == name) || status.adapters.contains_key(name) || is_adapter()
+}
+
+fn handle_adapters(config: &config::Config, root: &Path, status: &mut DaemonStatus) -> Result<(), DaemonError> {
+    let adapters = battery::load_devices(root)?
+        .into_iter()
+        .filter(|device| matches!(device.device_type, battery::DeviceType::Mains | battery::DeviceType::Usb));
+
+    for adapter in adapters {
+        let online = adapter.online.unwrap_or(false);
+        let env = HandlerEnv::new()
+            .var("DEVICE", &adapter.name)
+            .var("ONLINE", if online { "1" } else { "0" });
+
+        // The first reading only sets the baseline.
+        match status.adapters.insert(adapter.name.clone(), online) {
+            Some(false) if online => {
+                println!("[INFO] {} connected", adapter.name);
+                run_handler("AC connected", config.ac_connected_handler.as_ref(), &adapter.name, &env.var("EVENT", "ac-connected"));
+            }
+            Some(true) if !online => {
+                println!("[INFO] {} disconnected", adapter.name);
+                run_handler("AC disconnected", config.ac_disconnected_handler.as_ref(), &adapter.name, &env.var("EVENT", "ac-disconnected"));
+            }
+            _ => (),
+        }
     }
-    else if !status.batteries[bat].okay_handled && *capacity <= config.okay_capacity {
-        handle_okay(config, bat);
+
+    Ok(())
+}
+
+/// Cancels the countdown if power got connected, or performs its action once it has passed.
+fn handle_countdown(status: &mut DaemonStatus) {
+    let countdown = match status.countdown.take() {
+        Some(val) => val,
+        None => return,
+    };
+
+    if status.on_ac() {
+        println!("[INFO] Power connected, cancelled the {}", countdown.action);
+        // The battery may still be past the threshold when power goes away
+        // again, so the action has to be able to fire without a recharge.
+        if let Some(handled) = status.batteries
+            .get_mut(&countdown.battery)
+            .and_then(|bat| bat.handled.get_mut(countdown.threshold))
+        {
+            *handled = false;
+        }
+        return;
     }
-    else if !status.batteries[bat].good_handled {
-        handle_good(config, bat);
+    if Instant::now() < countdown.deadline {
+        status.countdown = Some(countdown);
+        return;
     }
 
-    Ok(())
+    println!("[INFO] Performing the {}", countdown.action);
+    if let Err(e) = countdown.action.perform() {
+        eprintln!("[ERROR] The {} failed: {e}", countdown.action);
+    }
 }
 
-fn handle_good(config: &config::Config, bat: &String) {
-    println!("[INFO] Good capacity of {bat}");
+fn handle_battery(config: &config::Config, device: &battery::Battery, capacity: u8, status: &mut DaemonStatus) -> Result<(), DaemonError> {
+    let bat = &device.name;
+    let bat_status = status.batteries
+        .entry(bat.clone())
+        .or_default();
 
-    if let Some(handler) = &config.good_capacity_handler {
-        let status = Command::new("bash")
-            .arg("-c")
-            .arg(format!("{} {}", handler, bat))
-            .status();
+    let time_left = bat_status.estimator.update(device, Instant::now());
+    let events = bat_status.update(config, bat, capacity, device.status, time_left);
+    let env = HandlerEnv::for_battery(device, bat_status);
 
-        match status {
-            Ok(_) => (),
-            Err(e) => eprintln!("[ERROR] Good capacity handler failed: {e}"),
+    for event in events {
+        match event {
+            Event::Threshold(i) => {
+                let threshold = &config.thresholds[i];
+                match (threshold.percentage, threshold.minutes) {
+                    (Some(percentage), _) => println!("[INFO] {bat} reached the {} threshold ({}%)", threshold.name, percentage),
+                    (None, minutes) => println!("[INFO] {bat} reached the {} threshold ({} minutes left)", threshold.name, minutes.unwrap_or(0)),
+                }
+                let env = env
+                    .var("EVENT", &threshold.name)
+                    .opt("THRESHOLD", threshold.percentage)
+                    .opt("THRESHOLD_MINUTES", threshold.minutes)
+                    .opt("ACTION", threshold.action);
+                if let (Some(action), None) = (threshold.action, &status.countdown) {
+                    println!("[WARN] Performing the {action} in {}s unless power is connected", config.action_countdown_secs);
+                    status.countdown = Some(Countdown {
+                        action,
+                        deadline: Instant::now() + Duration::from_secs(config.action_countdown_secs),
+                        battery: bat.clone(),
+                        threshold: i,
+                    });
+                }
+                if let Some(options) = threshold.notify.as_ref().and_then(config::Notify::options) {
+                    show_notification(config, &options, device, &env, &mut status.notifier, bat_status);
+                }
+                run_handler(&threshold.name, threshold.handler.as_ref(), bat, &env);
+            }
+            Event::ChargingStarted => {
+                println!("[INFO] {bat} started charging");
+                run_handler("Charging started", config.charging_started_handler.as_ref(), bat, &env.var("EVENT", "charging-started"));
+            }
+            Event::DischargingStarted => {
+                println!("[INFO] {bat} started discharging");
+                run_handler("Discharging started", config.discharging_started_handler.as_ref(), bat, &env.var("EVENT", "discharging-started"));
+            }
+            Event::Full => {
+                println!("[INFO] {bat} is full");
+                run_handler("Full", config.full_handler.as_ref(), bat, &env.var("EVENT", "full"));
+            }
         }
     }
+
+    Ok(())
 }
-fn handle_okay(config: &config::Config, bat: &String) {
-    println!("[INFO] Okay capacity");
 
-    if let Some(handler) = &config.okay_capacity_handler {
-        let status = Command::new("bash")
-            .arg("-c")
-            .arg(format!("{} {}", handler, bat))
-            .status();
+/// Applies the configured charge limit when the battery's differs from it.
+/// Some firmware resets the thresholds on resume or reboot, so this runs on
+/// every read rather than once at start. Nobody is there to answer a password
+/// prompt, so the helper is asked not to show one, and a failed write isn't
+/// retried before [`CHARGE_LIMIT_RETRY`] has passed.
+fn handle_charge_limit(config: &config::Config, root: &Path, bat: &str, status: &mut DaemonStatus) {
+    let limit = match config.charge_limit(bat) {
+        Some(val) if !val.is_empty() => val,
+        _ => return,
+    };
+    let bat_status = status.batteries
+        .entry(bat.to_string())
+        .or_default();
+    if bat_status.charge_limit_retry.is_some_and(|retry| Instant::now() < retry) {
+        return;
+    }
 
-        match status {
-            Ok(_) => (),
-            Err(e) => eprintln!("[ERROR] Okay capacity handler failed: {e}"),
+    let result = charge::ChargeControl::load(root, bat)
+        .map_err(|e| e.to_string())
+        .and_then(|control| {
+            if !control.differs(&limit) {
+                return Ok(false);
+            }
+            control.validate(&limit)?;
+            control.apply(&limit, false)?;
+            Ok(true)
+        });
+
+    match result {
+        Ok(applied) => {
+            if applied {
+                println!("[INFO] Applied the charge limit to {bat}");
+            }
+            bat_status.charge_limit_error = None;
+            bat_status.charge_limit_retry = None;
+        }
+        Err(e) => {
+            if bat_status.charge_limit_error.as_ref() != Some(&e) {
+                eprintln!("[ERROR] Applying the charge limit to {bat} failed: {e}");
+            }
+            bat_status.charge_limit_error = Some(e);
+            bat_status.charge_limit_retry = Some(Instant::now() + CHARGE_LIMIT_RETRY);
         }
     }
 }
-fn handle_bad(config: &config::Config, bat: &String) {
-    println!("[INFO] Bad capacity");
 
-    if let Some(handler) = &config.bad_capacity_handler {
-        let status = Command::new("bash")
-            .arg("-c")
-            .arg(format!("{} {}", handler, bat))
-            .status();
+/// Records the full capacity once a day, firing the replacement event when
+/// it drops below the replacement threshold. Since the records persist, the
+/// event fires once per battery rather than once per daemon start.
+fn handle_capacity_record(config: &config::Config, device: &battery::Battery, status: &mut DaemonStatus) {
+    let (log, record) = match (status.capacity_log.as_mut(), health::Record::of(device, history::now())) {
+        (Some(log), Some(record)) => (log, record),
+        _ => return,
+    };
 
-        match status {
-            Ok(_) => (),
-            Err(e) => eprintln!("[ERROR] Bad capacity handler failed: {e}"),
-        }
+    let (previous, record) = match log.record_daily(record) {
+        Ok(Some(val)) => val,
+        Ok(None) => return,
+        Err(e) => return eprintln!("[ERROR] Recording the full capacity failed: {e}"),
+    };
+
+    let threshold = config.health.replacement_percent as f64;
+    if record.percent() > threshold || previous.is_some_and(|p| p.percent() <= threshold) {
+        return;
+    }
+
+    let bat = &device.name;
+    println!(
+        "[WARN] {bat} is down to {:.1}% of its design capacity, below the {}% replacement threshold",
+        record.percent(),
+        config.health.replacement_percent,
+    );
+    let bat_status = status.batteries
+        .entry(bat.clone())
+        .or_default();
+    let env = HandlerEnv::for_battery(device, bat_status)
+        .var("EVENT", "replacement-due")
+        .var("HEALTH", format!("{:.1}", record.percent()));
+
+    if let Some(options) = config.health.notify.as_ref().and_then(config::Notify::options) {
+        show_notification(config, &options, device, &env, &mut status.notifier, bat_status);
     }
+    run_handler("Replacement due", config.health.handler.as_ref(), bat, &env);
 }
-fn handle_critical(config: &config::Config, bat: &String) {
-    println!("[INFO] Critical capacity");
 
-    if let Some(handler) = &config.critical_capacity_handler {
-        let status = Command::new("bash")
-            .arg("-c")
-            .arg(format!("{} {}", handler, bat))
-            .status();
+fn show_notification(
+    config: &config::Config,
+    options: &config::NotifyOptions,
+    device: &battery::Battery,
+    env: &HandlerEnv,
+    notifier: &mut Option<notify::Notifier>,
+    bat_status: &mut BatteryStatus,
+) {
+    let charging = matches!(device.status, Some(battery::Status::Charging | battery::Status::Full));
+    let notification = notify::Notification {
+        app_name: config.notifications.app_name.clone(),
+        summary: notify::render(options.summary.as_ref().unwrap_or(&config.notifications.summary), &env.vars),
+        body: notify::render(options.body.as_ref().unwrap_or(&config.notifications.body), &env.vars),
+        icon: options.icon.clone().unwrap_or_else(|| notify::battery_icon(device.capacity, charging)),
+        urgency: options.urgency.unwrap_or(notify::Urgency::Normal),
+        timeout_ms: config.notifications.timeout_ms,
+    };
+
+    if notifier.is_none() {
+        match notify::Notifier::connect() {
+            Ok(val) => *notifier = Some(val),
+            Err(e) => return eprintln!("[ERROR] Can't connect to the notification server: {e}"),
+        }
+    }
 
-        match status {
-            Ok(_) => (),
-            Err(e) => eprintln!("[ERROR] Critical capacity handler failed: {e}"),
+    match notifier.as_ref().map(|n| n.notify(&notification, bat_status.notification_id)) {
+        Some(Ok(id)) => bat_status.notification_id = id,
+        Some(Err(e)) => {
+            eprintln!("[ERROR] Showing a notification failed: {e}");
+            // Reconnect next time, in case the session bus went away.
+            *notifier = None;
         }
+        None => (),
     }
 }
 
+/// The `BATTERYCTL_*` environment variables passed to a handler.
+#[derive(Debug, Clone, Default)]
+struct HandlerEnv {
+    vars: Vec<(String, String)>,
+}
+
+impl HandlerEnv {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn for_battery(device: &battery::Battery, status: &BatteryStatus) -> Self {
+        Self::new()
+            .var("DEVICE", &device.name)
+            .opt("CAPACITY", device.capacity)
+            .opt("STATUS", device.status)
+            .var("LEVEL", status.level.as_deref().unwrap_or(""))
+            .var("PREVIOUS_LEVEL", status.previous_level.as_deref().unwrap_or(""))
+            .opt("TIME_TO_EMPTY", status.time_left.to_empty.map(|d| d.as_secs()))
+            .opt("TIME_TO_FULL", status.time_left.to_full.map(|d| d.as_secs()))
+            .opt("POWER_W", device.power_w().map(|w| format!("{:.2}", w)))
+            .opt("ENERGY_WH", device.energy_wh().map(|wh| format!("{:.2}", wh)))
+            .opt("VOLTAGE_V", device.voltage_v().map(|v| format!("{:.2}", v)))
+    }
+
+    fn var(&self, name: &str, value: impl ToString) -> Self {
+        let mut env = self.clone();
+        env.vars.retain(|(k, _)| k != name);
+        env.vars.push((name.to_string(), value.to_string()));
+        env
+    }
+
+    /// Sets a variable to the value, or to an empty string if it's unknown.
+    fn opt(&self, name: &str, value: Option<impl ToString>) -> Self {
+        self.var(name, value.map_or(String::new(), |v| v.to_string()))
+    }
+}
+
+fn run_handler(label: &str, handler: Option<&config::Handler>, device: &str, env: &HandlerEnv) {
+    if let Some(handler) = handler {
+        let vars: Vec<(String, String)> = env.vars
+            .iter()
+            .map(|(k, v)| (format!("BATTERYCTL_{}", k), v.clone()))
+            .collect();
+
+        handler::run_and_report(label, handler, device, &vars);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOOD: usize = 0;
+    const OKAY: usize = 1;
+    const BAD: usize = 2;
+    const CRITICAL: usize = 3;
+
+    fn config() -> config::Config {
+        let mut config = config::Config::new();
+        config.hysteresis = 2;
+        config
+    }
+
+    fn charging(capacity: u8) -> (u8, Option<battery::Status>) {
+        (capacity, Some(battery::Status::Charging))
+    }
+
+    fn discharging(capacity: u8) -> (u8, Option<battery::Status>) {
+        (capacity, Some(battery::Status::Discharging))
+    }
+
+    fn run_events(config: &config::Config, readings: &[(u8, Option<battery::Status>)]) -> Vec<Vec<Event>> {
+        let mut status = BatteryStatus::default();
+
+        readings
+            .iter()
+            .map(|(capacity, bat_status)| status.update(config, "BAT0", *capacity, *bat_status, battery::TimeLeft::default()))
+            .collect()
+    }
+
+    /// Runs a discharging capacity sequence, returning the threshold fired by each reading.
+    fn run(config: &config::Config, capacities: &[u8]) -> Vec<Option<usize>> {
+        let mut status = BatteryStatus::default();
+
+        capacities
+            .iter()
+            .map(|capacity| {
+                let fired = status.update_thresholds(config, "BAT0", *capacity, battery::TimeLeft::default(), true, false);
+                assert!(fired.len() <= 1, "{:?}", fired);
+                fired.first().copied()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fires_each_level_once_while_discharging() {
+        let fired = run(&config(), &[90, 80, 75, 74, 60, 50, 49, 21, 20, 19, 6, 5, 4, 0]);
+
+        assert_eq!(fired, vec![
+            None, None, Some(GOOD), None, None, Some(OKAY), None,
+            None, Some(BAD), None, None, Some(CRITICAL), None, None,
+        ]);
+    }
+
+    #[test]
+    fn does_not_refire_while_hovering_around_a_threshold() {
+        let fired = run(&config(), &[60, 51, 50, 51, 52, 50, 49, 51]);
+
+        assert_eq!(fired, vec![Some(GOOD), None, Some(OKAY), None, None, None, None, None]);
+    }
+
+    #[test]
+    fn rearms_above_threshold_plus_hysteresis() {
+        let fired = run(&config(), &[60, 50, 52, 50, 53, 50]);
+
+        assert_eq!(fired, vec![Some(GOOD), Some(OKAY), None, None, None, Some(OKAY)]);
+    }
+
+    #[test]
+    fn fires_only_the_most_severe_of_several_crossed_levels() {
+        let fired = run(&config(), &[90, 4, 10, 30]);
+
+        assert_eq!(fired, vec![None, Some(CRITICAL), None, None]);
+    }
+
+    #[test]
+    fn starting_below_a_threshold_fires_it_once() {
+        let fired = run(&config(), &[15, 14, 60, 50]);
+
+        assert_eq!(fired, vec![Some(BAD), None, None, Some(OKAY)]);
+    }
+
+    #[test]
+    fn zero_hysteresis_rearms_just_above_the_threshold() {
+        let mut config = config();
+        config.hysteresis = 0;
+
+        let fired = run(&config, &[20, 21, 20]);
+
+        assert_eq!(fired, vec![Some(BAD), None, Some(BAD)]);
+    }
+
+    #[test]
+    fn levels_do_not_fire_while_charging() {
+        let fired = run_events(&config(), &[discharging(30), charging(19), charging(4), discharging(4)]);
+
+        assert_eq!(fired, vec![
+            vec![Event::Threshold(OKAY)],
+            vec![Event::ChargingStarted],
+            vec![],
+            vec![Event::DischargingStarted, Event::Threshold(CRITICAL)],
+        ]);
+    }
+
+    #[test]
+    fn reports_status_changes_after_the_first_reading() {
+        let full = (100, Some(battery::Status::Full));
+        let fired = run_events(&config(), &[charging(99), charging(99), full, discharging(99)]);
+
+        assert_eq!(fired, vec![
+            vec![],
+            vec![],
+            vec![Event::Full],
+            vec![Event::DischargingStarted],
+        ]);
+    }
+
+    #[test]
+    fn rising_thresholds_fire_once_while_charging() {
+        let mut config = config();
+        config.thresholds = vec![config::Threshold::new("unplug", 80, config::Direction::Rising)];
+
+        let fired = run_events(&config, &[
+            charging(79), charging(80), charging(81), discharging(79), charging(80),
+            discharging(77), charging(80),
+        ]);
+
+        assert_eq!(fired, vec![
+            vec![],
+            vec![Event::Threshold(0)],
+            vec![],
+            vec![Event::DischargingStarted],
+            vec![Event::ChargingStarted],
+            vec![Event::DischargingStarted],
+            vec![Event::ChargingStarted, Event::Threshold(0)],
+        ]);
+    }
+
+    #[test]
+    fn thresholds_can_be_limited_to_one_battery() {
+        let mut config = config();
+        config.thresholds = vec![config::Threshold {
+            battery: Some("BAT1".to_string()),
+            ..config::Threshold::new("low", 20, config::Direction::Falling)
+        }];
+
+        assert_eq!(run(&config, &[10]), vec![None]);
+    }
+
+    #[test]
+    fn repeat_policies() {
+        let mut config = config();
+        config.thresholds = vec![
+            config::Threshold {
+                repeat: config::Repeat::Once,
+                ..config::Threshold::new("once", 50, config::Direction::Falling)
+            },
+            config::Threshold {
+                repeat: config::Repeat::EveryRead,
+                ..config::Threshold::new("every-read", 10, config::Direction::Falling)
+            },
+        ];
+
+        let fired = run(&config, &[50, 60, 50, 10, 9, 8]);
+
+        assert_eq!(fired, vec![Some(0), None, None, Some(1), Some(1), Some(1)]);
+    }
+
+    #[test]
+    fn thresholds_at_the_same_percentage_fire_together() {
+        let mut config = config();
+        config.thresholds = vec![
+            config::Threshold::new("notify", 20, config::Direction::Falling),
+            config::Threshold::new("log", 20, config::Direction::Falling),
+            config::Threshold::new("warn", 30, config::Direction::Falling),
+        ];
+        let mut status = BatteryStatus::default();
+
+        assert_eq!(status.update_thresholds(&config, "BAT0", 15, battery::TimeLeft::default(), true, false), vec![0, 1]);
+    }
+
+    fn time_left(name: &str, minutes: u32) -> config::Threshold {
+        config::Threshold {
+            percentage: None,
+            minutes: Some(minutes),
+            ..config::Threshold::new(name, 0, config::Direction::Falling)
+        }
+    }
+
+    #[test]
+    fn time_thresholds_fire_on_the_estimate() {
+        let mut config = config();
+        config.thresholds = vec![
+            time_left("soon", 30),
+            time_left("now", 10),
+            config::Threshold::new("low", 20, config::Direction::Falling),
+        ];
+        let mut status = BatteryStatus::default();
+        let mut fire = |minutes: Option<u64>| {
+            let time_left = battery::TimeLeft {
+                to_empty: minutes.map(|m| Duration::from_secs(m * 60)),
+                to_full: None,
+            };
+            status.update_thresholds(&config, "BAT0", 50, time_left, true, false)
+        };
+
+        assert_eq!(fire(None), Vec::<usize>::new());
+        assert_eq!(fire(Some(45)), Vec::<usize>::new());
+        assert_eq!(fire(Some(29)), vec![0]);
+        assert_eq!(fire(Some(31)), Vec::<usize>::new());
+        assert_eq!(fire(Some(5)), vec![1]);
+        assert_eq!(fire(Some(32)), Vec::<usize>::new());
+        assert_eq!(fire(Some(20)), vec![0]);
+    }
+
+    #[test]
+    fn cancelling_a_countdown_rearms_its_action() {
+        let mut config = config();
+        config.thresholds = vec![config::Threshold {
+            action: Some(power::Action::Hibernate),
+            ..config::Threshold::new("critical", 5, config::Direction::Falling)
+        }];
+        let device = battery::Battery {
+            name: "BAT0".to_string(),
+            status: Some(battery::Status::Discharging),
+            capacity: Some(4),
+            ..battery::Battery::default()
+        };
+        let mut status = DaemonStatus::new();
+
+        handle_battery(&config, &device, 4, &mut status).unwrap();
+        assert!(status.countdown.is_some());
+
+        status.adapters.insert("AC".to_string(), true);
+        handle_countdown(&mut status);
+        assert!(status.countdown.is_none());
+
+        status.adapters.insert("AC".to_string(), false);
+        handle_battery(&config, &device, 4, &mut status).unwrap();
+        assert!(matches!(status.countdown, Some(Countdown { action: power::Action::Hibernate, .. })));
+    }
+
+    #[test]
+    fn waits_before_retrying_a_failed_charge_limit() {
+        let root = tempfile::TempDir::new().unwrap();
+        let device = root.path().join("BAT0");
+        std::fs::create_dir(&device).unwrap();
+        let mut config = config();
+        config.charge_limits = vec![config::ChargeLimit {
+            battery: None,
+            start: None,
+            end: Some(80),
+            behaviour: None,
+        }];
+        let mut status = DaemonStatus::new();
+
+        handle_charge_limit(&config, root.path(), "BAT0", &mut status);
+        assert!(status.batteries["BAT0"].charge_limit_error.is_some());
+
+        // Even once the battery supports it, the limit waits for the retry.
+        std::fs::write(device.join("charge_control_end_threshold"), "100\n").unwrap();
+        handle_charge_limit(&config, root.path(), "BAT0", &mut status);
+        assert_eq!(std::fs::read_to_string(device.join("charge_control_end_threshold")).unwrap(), "100\n");
+
+        status.batteries.get_mut("BAT0").unwrap().charge_limit_retry = Some(Instant::now());
+        handle_charge_limit(&config, root.path(), "BAT0", &mut status);
+        assert_eq!(std::fs::read_to_string(device.join("charge_control_end_threshold")).unwrap(), "80");
+        assert!(status.batteries["BAT0"].charge_limit_error.is_none());
+    }
+
+    #[test]
+    fn wakes_only_for_batteries_and_adapters() {
+        let event = |name: &str, kind: &str| netlink::Uevent::parse(
+            format!("change@/devices/{name}\0SUBSYSTEM=power_supply\0POWER_SUPPLY_NAME={name}\0POWER_SUPPLY_TYPE={kind}\0").as_bytes(),
+        ).unwrap();
+        let mut config = config();
+        config.batteries = vec!["BAT0".to_string()];
+        let mut status = DaemonStatus::new();
+        status.adapters.insert("AC".to_string(), false);
+
+        assert!(is_relevant(&event("BAT0", "Battery"), &config, &status));
+        assert!(is_relevant(&event("AC", "Mains"), &config, &status));
+        // A charger plugged in after the last read isn't known yet.
+        assert!(is_relevant(&event("ucsi-source-psy-USBC000:001", "USB"), &config, &status));
+        assert!(!is_relevant(&event("BAT1", "Battery"), &config, &status));
+        assert!(!is_relevant(&event("hid-mouse-battery", "Battery"), &config, &status));
+    }
+}
@@ -0,0 +1,156 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::{
+    env,
+    io::{BufRead, BufReader, Read},
+    os::unix::process::{CommandExt, ExitStatusExt},
+    path::PathBuf,
+    process::{Command, ExitStatus, Stdio},
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+
+use crate::config::Handler;
+
+/// How often a running handler is checked for having exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long the output of an exited handler is waited for.
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The way a handler run ended.
+#[derive(Debug)]
+pub enum Outcome {
+    Exited(ExitStatus),
+    TimedOut,
+    Failed(std::io::Error),
+}
+
+/// Runs a handler with the device name as its last argument and `env` added
+/// to its environment, waiting for it to exit or killing it after its timeout.
+/// Its output goes to the daemon log, prefixed with `label`.
+pub fn run(label: &str, handler: &Handler, device: &str, env: &[(String, String)]) -> Outcome {
+    let mut cmd = command(handler, device);
+    cmd.envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // Its own process group, so a timeout also kills whatever a shell spawned.
+        .process_group(0);
+
+    let mut child = match cmd.spawn() {
+        Ok(val) => val,
+        Err(e) => return Outcome::Failed(e),
+    };
+
+    let stdout = forward_lines(child.stdout.take(), label.to_string(), false);
+    let stderr = forward_lines(child.stderr.take(), label.to_string(), true);
+
+    let deadline = Instant::now() + handler.timeout();
+    let outcome = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Outcome::Exited(status),
+            Ok(None) if Instant::now() >= deadline => {
+                unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+                let _ = child.wait();
+                break Outcome::TimedOut;
+            }
+            Ok(None) => sleep(POLL_INTERVAL),
+            Err(e) => break Outcome::Failed(e),
+        }
+    };
+
+    // Whatever the handler started in the background keeps the pipes open,
+    // so the forwarders are left running once the rest of the output is in.
+    let drained = Instant::now() + DRAIN_TIMEOUT;
+    while !(stdout.is_finished() && stderr.is_finished()) && Instant::now() < drained.min(deadline) {
+        sleep(POLL_INTERVAL);
+    }
+
+    outcome
+}
+
+/// Runs a handler and logs how it ended.
+pub fn run_and_report(label: &str, handler: &Handler, device: &str, env: &[(String, String)]) {
+    match run(label, handler, device, env) {
+        Outcome::Exited(status) if status.success() => (),
+        Outcome::Exited(status) => match (status.code(), status.signal()) {
+            (Some(code), _) => eprintln!("[ERROR] {label} handler exited with status {code}"),
+            (None, Some(signal)) => eprintln!("[ERROR] {label} handler was killed by signal {signal}"),
+            (None, None) => eprintln!("[ERROR] {label} handler failed: {status}"),
+        },
+        Outcome::TimedOut => eprintln!(
+            "[ERROR] {label} handler didn't finish within {}s and was killed",
+            handler.timeout().as_secs(),
+        ),
+        Outcome::Failed(e) => eprintln!("[ERROR] {label} handler failed: {e}"),
+    }
+}
+
+fn command(handler: &Handler, device: &str) -> Command {
+    if let Some(line) = handler.shell() {
+        // The device is passed as "$1" rather than pasted into the command line.
+        let mut cmd = Command::new("/bin/sh");
+        cmd.arg("-c")
+            .arg(format!("{} \"$@\"", line))
+            .arg("sh")
+            .arg(device);
+        return cmd;
+    }
+
+    let argv = handler.command().unwrap_or_default();
+    let mut cmd = Command::new(expand_tilde(argv.first().map_or("", String::as_str)));
+    cmd.args(argv.iter().skip(1).map(|arg| expand_tilde(arg)))
+        .arg(device);
+    cmd
+}
+
+/// Expands a leading `~` to the home directory, like a shell would.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    let home = match env::var("HOME") {
+        Ok(val) => PathBuf::from(val),
+        Err(_) => return PathBuf::from(path),
+    };
+
+    if path == "~" {
+        home
+    } else if let Some(rest) = path.strip_prefix("~/") {
+        home.join(rest)
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+fn forward_lines(pipe: Option<impl Read + Send + 'static>, label: String, is_stderr: bool) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let pipe = match pipe {
+            Some(val) => val,
+            None => return,
+        };
+
+        for line in BufReader::new(pipe).lines().map_while(Result::ok) {
+            if is_stderr {
+                eprintln!("[WARN] {label} handler: {line}");
+            } else {
+                println!("[INFO] {label} handler: {line}");
+            }
+        }
+    })
+}
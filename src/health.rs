@@ -0,0 +1,427 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::{collections::HashMap, fmt, fs, io::Write, path::PathBuf};
+
+use crate::battery::{Battery, Status};
+use crate::history::{self, HistoryError};
+
+const CAPACITY_LOG_NAME: &str = "capacity.tsv";
+const CAPACITY_LOG_HEADER: &str = "# timestamp\tdevice\tfull\tfull_design\tcycle_count";
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+const DAYS_PER_MONTH: f64 = 30.44;
+
+/// A grade of how worn a battery is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verdict {
+    Good,
+    Fair,
+    Poor,
+    Replace,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Verdict::Good => write!(f, "good"),
+            Verdict::Fair => write!(f, "fair"),
+            Verdict::Poor => write!(f, "poor"),
+            Verdict::Replace => write!(f, "replace"),
+        }
+    }
+}
+
+impl Verdict {
+    /// Grades the full capacity left in percent of the design capacity.
+    fn from_health(percent: f64) -> Self {
+        match percent {
+            p if p >= 80.0 => Verdict::Good,
+            p if p >= 65.0 => Verdict::Fair,
+            p if p >= 50.0 => Verdict::Poor,
+            _ => Verdict::Replace,
+        }
+    }
+}
+
+/// The full and design capacity, in Wh or Ah depending on what the driver reports.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capacity {
+    pub full: f64,
+    pub design: f64,
+    pub unit: &'static str,
+}
+
+/// The wear report of a battery. Anything the driver doesn't expose is `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Health {
+    pub device: String,
+    pub capacity: Option<Capacity>,
+    pub cycle_count: Option<u32>,
+    pub technology: Option<String>,
+    pub manufacture_date: Option<String>,
+    pub voltage_v: Option<f64>,
+    pub voltage_min_design_v: Option<f64>,
+    pub verdict: Option<Verdict>,
+}
+
+impl Health {
+    pub fn of(bat: &Battery) -> Self {
+        let capacity = match (bat.energy_full, bat.energy_full_design, bat.charge_full, bat.charge_full_design) {
+            (Some(full), Some(design), _, _) if design != 0 => Some(Capacity {
+                full: full as f64 / 1e6,
+                design: design as f64 / 1e6,
+                unit: "Wh",
+            }),
+            (_, _, Some(full), Some(design)) if design != 0 => Some(Capacity {
+                full: full as f64 / 1e6,
+                design: design as f64 / 1e6,
+                unit: "Ah",
+            }),
+            _ => None,
+        };
+
+        let manufacture_date = match (bat.manufacture_year, bat.manufacture_month, bat.manufacture_day) {
+            (Some(year), Some(month), Some(day)) => Some(format!("{:04}-{:02}-{:02}", year, month, day)),
+            (Some(year), Some(month), None) => Some(format!("{:04}-{:02}", year, month)),
+            (Some(year), None, _) => Some(format!("{:04}", year)),
+            _ => None,
+        };
+
+        let mut health = Self {
+            device: bat.name.clone(),
+            capacity,
+            cycle_count: bat.cycle_count,
+            technology: bat.technology.clone(),
+            manufacture_date,
+            voltage_v: bat.voltage_v(),
+            voltage_min_design_v: bat.voltage_min_design.map(|v| v as f64 / 1e6),
+            verdict: None,
+        };
+
+        health.verdict = health.health_percent().map(|percent| {
+            let verdict = Verdict::from_health(percent);
+            // A cell sagging below its design minimum under load won't last long, however much it holds.
+            match health.voltage_margin_percent() {
+                Some(margin) if margin < 0.0 && bat.status == Some(Status::Discharging) => verdict.max(Verdict::Poor),
+                _ => verdict,
+            }
+        });
+
+        health
+    }
+
+    /// The full capacity left in percent of the design capacity.
+    pub fn health_percent(&self) -> Option<f64> {
+        self.capacity.map(|c| c.full / c.design * 100.0)
+    }
+
+    /// The capacity lost to wear in percent of the design capacity.
+    pub fn wear_percent(&self) -> Option<f64> {
+        self.health_percent().map(|health| (100.0 - health).max(0.0))
+    }
+
+    /// How far the voltage sits above the design minimum, in percent of it.
+    /// Negative when it sags below.
+    pub fn voltage_margin_percent(&self) -> Option<f64> {
+        match (self.voltage_v, self.voltage_min_design_v) {
+            (Some(now), Some(min)) if min != 0.0 => Some((now / min - 1.0) * 100.0),
+            _ => None,
+        }
+    }
+}
+
+/// The full capacity of a battery on one day, in µWh or µAh, whichever the driver reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub timestamp: u64,
+    pub device: String,
+    pub full: u64,
+    pub full_design: u64,
+    pub cycle_count: Option<u32>,
+}
+
+impl Record {
+    pub fn of(bat: &Battery, timestamp: u64) -> Option<Self> {
+        let (full, full_design) = match (bat.energy_full, bat.energy_full_design, bat.charge_full, bat.charge_full_design) {
+            (Some(full), Some(design), _, _) if design != 0 => (full, design),
+            (_, _, Some(full), Some(design)) if design != 0 => (full, design),
+            _ => return None,
+        };
+
+        Some(Self {
+            timestamp,
+            device: bat.name.clone(),
+            full,
+            full_design,
+            cycle_count: bat.cycle_count,
+        })
+    }
+
+    /// The full capacity in percent of the design capacity.
+    pub fn percent(&self) -> f64 {
+        self.full as f64 / self.full_design as f64 * 100.0
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}",
+            self.timestamp,
+            self.device,
+            self.full,
+            self.full_design,
+            self.cycle_count.map(|c| c.to_string()).unwrap_or_default(),
+        )
+    }
+
+    fn parse(line: &str) -> Result<Self, HistoryError> {
+        let invalid = || HistoryError::Parse(format!("Invalid capacity record '{}'", line));
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 5 {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            timestamp: fields[0].parse().map_err(|_| invalid())?,
+            device: fields[1].to_string(),
+            full: fields[2].parse().map_err(|_| invalid())?,
+            full_design: fields[3].parse().map_err(|_| invalid())?,
+            cycle_count: match fields[4] {
+                "" => None,
+                count => Some(count.parse().map_err(|_| invalid())?),
+            },
+        })
+    }
+}
+
+/// The daily full capacity records in `$XDG_STATE_HOME/batteryctl/capacity.tsv`.
+/// At one line per battery and day it stays small, so it isn't rotated.
+pub struct CapacityLog {
+    path: PathBuf,
+    /// The last record of each battery, read from the file on first use.
+    last: Option<HashMap<String, Record>>,
+}
+
+impl CapacityLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, last: None }
+    }
+
+    pub fn open() -> Self {
+        Self::new(history::state_dir().join(CAPACITY_LOG_NAME))
+    }
+
+    /// Reads every record, oldest first, skipping lines that don't parse.
+    pub fn read(&self) -> Result<Vec<Record>, HistoryError> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(val) => val,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(contents
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| match Record::parse(line) {
+                Ok(val) => Some(val),
+                Err(e) => {
+                    eprintln!("[WARN] Skipping a line of {}: {}", self.path.display(), e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Appends a record unless the battery already has one from the last day.
+    /// Returns the previous record of the battery along with the new one when
+    /// it's written.
+    pub fn record_daily(&mut self, record: Record) -> Result<Option<(Option<Record>, Record)>, HistoryError> {
+        if self.last.is_none() {
+            let last = self.read()?
+                .into_iter()
+                .map(|record| (record.device.clone(), record))
+                .collect();
+            self.last = Some(last);
+        }
+        let last = self.last.get_or_insert_with(HashMap::new);
+
+        let previous = last.get(&record.device).cloned();
+        if previous.as_ref().is_some_and(|p| record.timestamp.saturating_sub(p.timestamp) < SECS_PER_DAY) {
+            return Ok(None);
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let new = !self.path.exists();
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut contents = String::new();
+        if new {
+            contents.push_str(CAPACITY_LOG_HEADER);
+            contents.push('\n');
+        }
+        contents.push_str(&record.to_line());
+        contents.push('\n');
+        file.write_all(contents.as_bytes())?;
+
+        last.insert(record.device.clone(), record.clone());
+        Ok(Some((previous, record)))
+    }
+}
+
+/// When the full capacity will drop to the replacement threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// It's already at or below it.
+    Reached,
+    /// Around this timestamp.
+    At(u64),
+    /// It isn't decreasing.
+    Never,
+}
+
+/// How fast a battery loses full capacity, fitted over its daily records.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trend {
+    pub days: u64,
+    pub records: usize,
+    pub percent_per_month: f64,
+    /// The loss per 100 charge cycles, if the driver reports a changing cycle count.
+    pub percent_per_100_cycles: Option<f64>,
+    pub replacement: Projection,
+}
+
+impl Trend {
+    /// Fits a line through the records of one battery. Needs at least two
+    /// records a day or more apart.
+    pub fn of(records: &[Record], replacement_percent: u8) -> Option<Self> {
+        let (first, last) = (records.first()?, records.last()?);
+        let days = last.timestamp.saturating_sub(first.timestamp) / SECS_PER_DAY;
+        if days == 0 {
+            return None;
+        }
+
+        let by_day: Vec<(f64, f64)> = records
+            .iter()
+            .map(|r| (r.timestamp.saturating_sub(first.timestamp) as f64 / SECS_PER_DAY as f64, r.percent()))
+            .collect();
+        let (slope, intercept) = fit(&by_day)?;
+
+        let by_cycles: Vec<(f64, f64)> = records
+            .iter()
+            .filter_map(|r| r.cycle_count.map(|c| (c as f64, r.percent())))
+            .collect();
+        let percent_per_100_cycles = fit(&by_cycles).map(|(slope, _)| slope * 100.0);
+
+        let current = slope * by_day.last()?.0 + intercept;
+        let replacement = match replacement_percent as f64 {
+            threshold if current <= threshold => Projection::Reached,
+            _ if slope >= 0.0 => Projection::Never,
+            threshold => {
+                let days_left = (threshold - current) / slope;
+                Projection::At(last.timestamp + (days_left * SECS_PER_DAY as f64) as u64)
+            }
+        };
+
+        Some(Self {
+            days,
+            records: records.len(),
+            percent_per_month: slope * DAYS_PER_MONTH,
+            percent_per_100_cycles,
+            replacement,
+        })
+    }
+}
+
+/// A least squares line through the points, as its slope and intercept.
+/// `None` if the x values don't vary.
+fn fit(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let var_x: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    if points.len() < 2 || var_x == 0.0 {
+        return None;
+    }
+
+    let cov: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    let slope = cov / var_x;
+    Some((slope, mean_y - slope * mean_x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(day: u64, percent: u64, cycle_count: Option<u32>) -> Record {
+        Record {
+            timestamp: 1_700_000_000 + day * SECS_PER_DAY,
+            device: "BAT0".to_string(),
+            full: percent * 1000,
+            full_design: 100_000,
+            cycle_count,
+        }
+    }
+
+    #[test]
+    fn projects_the_replacement_date() {
+        let records = [record(0, 90, Some(100)), record(30, 89, Some(110)), record(60, 88, Some(120))];
+
+        let trend = Trend::of(&records, 70).unwrap();
+
+        assert_eq!(trend.days, 60);
+        assert!((trend.percent_per_month - -1.0147).abs() < 0.001, "{}", trend.percent_per_month);
+        assert!((trend.percent_per_100_cycles.unwrap() - -10.0).abs() < 0.001);
+        // 18% more at 1% per 30 days.
+        assert_eq!(trend.replacement, Projection::At(records[2].timestamp + 540 * SECS_PER_DAY));
+    }
+
+    #[test]
+    fn needs_records_on_different_days() {
+        assert_eq!(Trend::of(&[record(0, 90, None)], 70), None);
+        assert_eq!(Trend::of(&[record(0, 60, None), record(1, 60, None)], 70).unwrap().replacement, Projection::Reached);
+        assert_eq!(Trend::of(&[record(0, 80, None), record(1, 80, None)], 70).unwrap().replacement, Projection::Never);
+    }
+
+    #[test]
+    fn records_once_a_day() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut log = CapacityLog::new(dir.path().join(CAPACITY_LOG_NAME));
+
+        assert_eq!(log.record_daily(record(0, 90, None)).unwrap(), Some((None, record(0, 90, None))));
+        assert_eq!(log.record_daily(Record { timestamp: record(1, 89, None).timestamp - 1, ..record(0, 89, None) }).unwrap(), None);
+        assert_eq!(log.record_daily(record(1, 89, None)).unwrap(), Some((Some(record(0, 90, None)), record(1, 89, None))));
+
+        // A restarted daemon picks up where the file left off.
+        let mut log = CapacityLog::new(dir.path().join(CAPACITY_LOG_NAME));
+        assert_eq!(log.record_daily(record(1, 89, None)).unwrap(), None);
+        assert_eq!(log.read().unwrap(), vec![record(0, 90, None), record(1, 89, None)]);
+    }
+
+    #[test]
+    fn grades_by_the_capacity_left() {
+        assert_eq!(Verdict::from_health(104.0), Verdict::Good);
+        assert_eq!(Verdict::from_health(80.0), Verdict::Good);
+        assert_eq!(Verdict::from_health(79.9), Verdict::Fair);
+        assert_eq!(Verdict::from_health(50.0), Verdict::Poor);
+        assert_eq!(Verdict::from_health(49.9), Verdict::Replace);
+    }
+}
@@ -0,0 +1,239 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! A privileged D-Bus service that writes a few battery settings for
+//! unprivileged users, once polkit authorizes them.
+
+use std::{collections::HashMap, fmt, path::{Path, PathBuf}};
+
+use zbus::{fdo, message::Header, zvariant::Value};
+
+use crate::attribute;
+use crate::battery::Properties;
+
+pub const BUS_NAME: &str = "io.github.DucktectiveCZ.Batteryctl1";
+const OBJECT_PATH: &str = "/io/github/DucktectiveCZ/Batteryctl1";
+
+/// The polkit action every write has to be authorized for.
+pub const POLKIT_ACTION: &str = "io.github.ducktectivecz.batteryctl.set-charge-settings";
+const POLKIT_NAME: &str = "org.freedesktop.PolicyKit1";
+const POLKIT_PATH: &str = "/org/freedesktop/PolicyKit1/Authority";
+const POLKIT_AUTHORITY: &str = "org.freedesktop.PolicyKit1.Authority";
+/// Lets polkit ask the user's authentication agent for a password.
+const POLKIT_ALLOW_USER_INTERACTION: u32 = 1;
+
+/// The only properties the helper writes.
+const WRITABLE: &[&str] = &[
+    "charge_control_start_threshold",
+    "charge_control_end_threshold",
+    "charge_start_threshold",
+    "charge_stop_threshold",
+    "charge_behaviour",
+    "charge_type",
+    "charge_types",
+];
+
+#[derive(Debug)]
+pub enum HelperError {
+    NotInstalled,
+    Denied(String),
+    Rejected(String),
+    DBus(String),
+}
+
+impl std::error::Error for HelperError { }
+
+impl fmt::Display for HelperError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotInstalled => write!(f, "The batteryctl helper isn't installed"),
+            Self::Denied(e) => write!(f, "Not authorized: {}", e),
+            Self::Rejected(e) => write!(f, "{}", e),
+            Self::DBus(e) => write!(f, "D-Bus error: {}", e),
+        }
+    }
+}
+
+impl From<zbus::Error> for HelperError {
+    fn from(e: zbus::Error) -> Self {
+        let (name, description) = match &e {
+            zbus::Error::MethodError(name, description, _) => (name.as_str(), description.clone().unwrap_or_default()),
+            _ => return Self::DBus(e.to_string()),
+        };
+
+        match name {
+            "org.freedesktop.DBus.Error.ServiceUnknown" => Self::NotInstalled,
+            "org.freedesktop.DBus.Error.AccessDenied" => Self::Denied(description),
+            "org.freedesktop.DBus.Error.InvalidArgs" => Self::Rejected(description),
+            _ => Self::DBus(description),
+        }
+    }
+}
+
+/// Asks the helper to write a property, which it does after checking the
+/// value and asking polkit whether the caller may change it. Unless
+/// `interactive`, polkit refuses right away instead of asking for a password,
+/// which would block until someone answers.
+pub fn set_property(device: &str, name: &str, value: &str, interactive: bool) -> Result<(), HelperError> {
+    let method = match interactive {
+        true => "SetProperty",
+        false => "SetPropertyNoninteractive",
+    };
+    let conn = zbus::blocking::Connection::system()?;
+    conn.call_method(
+        Some(BUS_NAME),
+        OBJECT_PATH,
+        Some(BUS_NAME),
+        method,
+        &(device, name, value),
+    )?;
+
+    Ok(())
+}
+
+/// Checks a write the way `set-property` does, and that it's one the helper makes.
+fn check(root: &Path, device: &str, name: &str, value: &str) -> Result<Properties, String> {
+    // The device is joined to the sysfs root, so it can't be allowed to leave it.
+    if device.is_empty() || device.contains('/') || device == "." || device == ".." {
+        return Err(format!("Invalid device name '{}'", device));
+    }
+    if !WRITABLE.contains(&name) {
+        return Err(format!("The helper doesn't write '{}', only {}", name, WRITABLE.join(", ")));
+    }
+
+    let props = Properties::load(root, device).map_err(|e| e.to_string())?;
+    match props.writable() {
+        Ok(val) if val.iter().any(|w| w == name) => (),
+        Ok(_) => return Err(format!("{} has no writable '{}'", device, name)),
+        Err(e) => return Err(e.to_string()),
+    }
+    attribute::validate(&props, name, value)?;
+
+    Ok(props)
+}
+
+struct Helper {
+    root: PathBuf,
+}
+
+#[zbus::interface(name = "io.github.DucktectiveCZ.Batteryctl1")]
+impl Helper {
+    async fn set_property(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] conn: &zbus::Connection,
+        device: String,
+        name: String,
+        value: String,
+    ) -> fdo::Result<()> {
+        self.write(&header, conn, device, name, value, POLKIT_ALLOW_USER_INTERACTION).await
+    }
+
+    /// Like `SetProperty`, but fails instead of asking for a password, for
+    /// callers nobody is watching, like the daemon.
+    async fn set_property_noninteractive(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] conn: &zbus::Connection,
+        device: String,
+        name: String,
+        value: String,
+    ) -> fdo::Result<()> {
+        self.write(&header, conn, device, name, value, 0).await
+    }
+}
+
+impl Helper {
+    async fn write(
+        &self,
+        header: &Header<'_>,
+        conn: &zbus::Connection,
+        device: String,
+        name: String,
+        value: String,
+        polkit_flags: u32,
+    ) -> fdo::Result<()> {
+        let props = check(&self.root, &device, &name, &value).map_err(fdo::Error::InvalidArgs)?;
+        let sender = match header.sender() {
+            Some(val) => val.to_string(),
+            None => return Err(fdo::Error::AccessDenied("The caller is unknown".to_string())),
+        };
+
+        let mut subject: HashMap<&str, Value> = HashMap::new();
+        subject.insert("name", Value::from(sender.as_str()));
+        let details = HashMap::from([
+            ("device", device.as_str()),
+            ("property", name.as_str()),
+            ("value", value.as_str()),
+        ]);
+        let reply = conn.call_method(
+            Some(POLKIT_NAME),
+            POLKIT_PATH,
+            Some(POLKIT_AUTHORITY),
+            "CheckAuthorization",
+            &(("system-bus-name", subject), POLKIT_ACTION, details, polkit_flags, ""),
+        ).await.map_err(|e| fdo::Error::AccessDenied(format!("polkit can't authorize the write: {e}")))?;
+        let (authorized, _, _) = reply
+            .body()
+            .deserialize::<(bool, bool, HashMap<String, String>)>()
+            .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+        if !authorized {
+            println!("[WARN] Refused to write '{value}' to {name} of {device} for {sender}");
+            return Err(fdo::Error::AccessDenied(format!("Changing the {} of {} needs authorization", name, device)));
+        }
+
+        props.write(&name, &value).map_err(|e| fdo::Error::Failed(e.to_string()))?;
+        println!("[INFO] Wrote '{value}' to {name} of {device} for {sender}");
+
+        Ok(())
+    }
+}
+
+/// Serves the helper on the system bus until it's killed. D-Bus starts it as
+/// root when it's first called.
+pub fn serve(root: &Path) -> Result<(), HelperError> {
+    let _conn = zbus::blocking::connection::Builder::system()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Helper { root: root.to_path_buf() })?
+        .build()?;
+    println!("[INFO] Serving {} for {}", BUS_NAME, root.display());
+
+    loop {
+        std::thread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_checks_charge_settings_of_plain_devices() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("BAT0")).unwrap();
+        std::fs::write(dir.path().join("BAT0").join("charge_control_end_threshold"), "100\n").unwrap();
+        std::fs::write(dir.path().join("BAT0").join("alarm"), "0\n").unwrap();
+
+        assert!(check(dir.path(), "BAT0", "charge_control_end_threshold", "80").is_ok());
+        assert!(check(dir.path(), "BAT0", "charge_control_end_threshold", "180").is_err());
+        assert!(check(dir.path(), "BAT0", "alarm", "1").is_err());
+        assert!(check(dir.path(), "../BAT0", "charge_control_end_threshold", "80").is_err());
+        assert!(check(dir.path(), "..", "charge_control_end_threshold", "80").is_err());
+    }
+}
@@ -0,0 +1,422 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::{
+    env, fmt, fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+
+use crate::battery;
+use crate::config;
+
+const FILE_PREFIX: &str = "history";
+const FILE_EXTENSION: &str = "tsv";
+const HEADER: &str = "# timestamp\tdevice\tcapacity\tstatus\tenergy_uwh\tpower_uw\tvoltage_uv\ttemp_dc";
+
+#[derive(Debug)]
+pub enum HistoryError {
+    IO(String),
+    Parse(String),
+}
+
+impl std::error::Error for HistoryError { }
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::IO(e) => write!(f, "IO error: {}", e),
+            Self::Parse(e) => write!(f, "Parse error: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for HistoryError {
+    fn from(e: io::Error) -> Self {
+        Self::IO(e.to_string())
+    }
+}
+
+/// A single reading of a battery, in the units of the power_supply ABI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub device: String,
+    pub capacity: Option<u8>,
+    pub status: Option<battery::Status>,
+    pub energy_now: Option<u64>,
+    /// The magnitude of the power draw, whichever direction it flows.
+    pub power_now: Option<u64>,
+    pub voltage_now: Option<u64>,
+    /// The temperature in tenths of a degree Celsius.
+    pub temp: Option<i32>,
+}
+
+impl Sample {
+    pub fn of(bat: &battery::Battery, timestamp: u64) -> Self {
+        Self {
+            timestamp,
+            device: bat.name.clone(),
+            capacity: bat.capacity,
+            status: bat.status,
+            // Batteries reporting charge are converted, so every sample is comparable.
+            energy_now: bat.energy_wh().map(|wh| (wh * 1e6).round() as u64),
+            power_now: bat.power_w().map(|w| (w * 1e6).round() as u64),
+            voltage_now: bat.voltage_now,
+            temp: bat.temp,
+        }
+    }
+
+    /// Formats the sample as a line of the history file, leaving unknown values empty.
+    pub fn to_line(&self) -> String {
+        let opt = |value: Option<String>| value.unwrap_or_default();
+
+        [
+            self.timestamp.to_string(),
+            self.device.clone(),
+            opt(self.capacity.map(|v| v.to_string())),
+            opt(self.status.map(|v| v.to_string())),
+            opt(self.energy_now.map(|v| v.to_string())),
+            opt(self.power_now.map(|v| v.to_string())),
+            opt(self.voltage_now.map(|v| v.to_string())),
+            opt(self.temp.map(|v| v.to_string())),
+        ].join("\t")
+    }
+
+    pub fn parse(line: &str) -> Result<Self, HistoryError> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 8 {
+            return Err(HistoryError::Parse(format!("Expected 8 fields in '{}'", line)));
+        }
+
+        fn opt<T: std::str::FromStr>(field: &str) -> Result<Option<T>, HistoryError> {
+            if field.is_empty() {
+                return Ok(None);
+            }
+            field
+                .parse()
+                .map(Some)
+                .map_err(|_| HistoryError::Parse(format!("Invalid value '{}'", field)))
+        }
+
+        Ok(Self {
+            timestamp: opt(fields[0])?.ok_or_else(|| HistoryError::Parse("Missing timestamp".to_string()))?,
+            device: fields[1].to_string(),
+            capacity: opt(fields[2])?,
+            status: opt(fields[3])?,
+            energy_now: opt(fields[4])?,
+            power_now: opt(fields[5])?,
+            voltage_now: opt(fields[6])?,
+            temp: opt(fields[7])?,
+        })
+    }
+}
+
+/// The rotated sample files in a state directory.
+///
+/// Samples are appended to `history.tsv`. Once it outgrows the size limit or
+/// its oldest sample passes the age limit it becomes `history.1.tsv` and the
+/// older files move up by one. Files past the file limit are deleted, and so
+/// are those last written before the age limit.
+pub struct History {
+    dir: PathBuf,
+    options: config::History,
+}
+
+impl History {
+    pub fn new(dir: PathBuf, options: config::History) -> Self {
+        Self { dir, options }
+    }
+
+    /// The history in `$XDG_STATE_HOME/batteryctl`.
+    pub fn open(options: config::History) -> Self {
+        Self::new(state_dir(), options)
+    }
+
+    fn path(&self, index: u32) -> PathBuf {
+        match index {
+            0 => self.dir.join(format!("{}.{}", FILE_PREFIX, FILE_EXTENSION)),
+            i => self.dir.join(format!("{}.{}.{}", FILE_PREFIX, i, FILE_EXTENSION)),
+        }
+    }
+
+    /// The existing history files, oldest first.
+    pub fn files(&self) -> Vec<PathBuf> {
+        (0..=self.options.max_files)
+            .rev()
+            .map(|i| self.path(i))
+            .filter(|path| path.exists())
+            .collect()
+    }
+
+    /// Reads every recorded sample, oldest first. Lines that don't parse, like
+    /// one cut short by a crash, are skipped with a warning.
+    pub fn read(&self) -> Result<Vec<Sample>, HistoryError> {
+        let mut samples = Vec::new();
+
+        for path in self.files() {
+            let contents = fs::read_to_string(&path)?;
+            let lines = contents
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+            for (i, line) in lines {
+                match Sample::parse(line) {
+                    Ok(val) => samples.push(val),
+                    Err(e) => eprintln!("[WARN] Skipping line {} of {}: {}", i + 1, path.display(), e),
+                }
+            }
+        }
+
+        Ok(samples)
+    }
+
+    pub fn append(&self, samples: &[Sample]) -> Result<(), HistoryError> {
+        fs::create_dir_all(&self.dir)?;
+
+        let current = self.path(0);
+        let size = fs::metadata(&current).map(|m| m.len()).unwrap_or(0);
+        let expired = first_timestamp(&current).is_some_and(|t| now().saturating_sub(t) > self.max_age().as_secs());
+        if size >= self.options.max_size_kb * 1024 || expired {
+            self.rotate()?;
+        }
+        self.prune()?;
+
+        let new = !current.exists();
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&current)?;
+
+        let mut contents = String::new();
+        if new {
+            contents.push_str(HEADER);
+            contents.push('\n');
+        }
+        for sample in samples {
+            contents.push_str(&sample.to_line());
+            contents.push('\n');
+        }
+        // A single write keeps concurrent readers from seeing half a line.
+        file.write_all(contents.as_bytes())?;
+
+        Ok(())
+    }
+
+    fn rotate(&self) -> Result<(), HistoryError> {
+        let _ = fs::remove_file(self.path(self.options.max_files));
+        for i in (0..self.options.max_files).rev() {
+            if self.path(i).exists() {
+                fs::rename(self.path(i), self.path(i + 1))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the rotated files with no sample newer than the age limit.
+    fn prune(&self) -> Result<(), HistoryError> {
+        for i in 1..=self.options.max_files {
+            let modified = fs::metadata(self.path(i)).and_then(|m| m.modified());
+            if modified.is_ok_and(|t| t.elapsed().unwrap_or_default() > self.max_age()) {
+                fs::remove_file(self.path(i))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn max_age(&self) -> Duration {
+        Duration::from_secs(self.options.max_age_days * 24 * 60 * 60)
+    }
+}
+
+/// The timestamp of the first sample in a history file, if it has one.
+fn first_timestamp(path: &Path) -> Option<u64> {
+    let file = fs::File::open(path).ok()?;
+    let line = io::BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))?;
+
+    Sample::parse(&line).ok().map(|sample| sample.timestamp)
+}
+
+/// The seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Parses a point in time for filtering the history: a Unix timestamp, a
+/// local date like `2024-05-01` or `2024-05-01T14:30`, or a duration before
+/// `now` like `90m`, `12h`, `7d` or `2w`.
+pub fn parse_time(s: &str, now: u64) -> Result<u64, String> {
+    let invalid = || format!(
+        "Invalid time '{}'. Use a Unix timestamp, a date like 2024-05-01 or 2024-05-01T14:30, or a duration like 12h or 7d.",
+        s,
+    );
+
+    if let Ok(timestamp) = s.parse::<u64>() {
+        return Ok(timestamp);
+    }
+
+    let units = [('s', 1), ('m', 60), ('h', 60 * 60), ('d', 24 * 60 * 60), ('w', 7 * 24 * 60 * 60)];
+    for (suffix, seconds) in units {
+        if let Some(count) = s.strip_suffix(suffix).and_then(|n| n.parse::<u64>().ok()) {
+            return Ok(now.saturating_sub(count * seconds));
+        }
+    }
+
+    let datetime = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().and_then(|d| d.and_hms_opt(0, 0, 0)))
+        .ok_or_else(invalid)?;
+
+    // The earliest reading of times that repeat when the clocks go back.
+    match Local.from_local_datetime(&datetime).earliest() {
+        Some(val) => Ok(val.timestamp().max(0) as u64),
+        None => Err(invalid()),
+    }
+}
+
+/// Formats a timestamp as a local RFC 3339 time.
+pub fn format_time(timestamp: u64) -> String {
+    match Local.timestamp_opt(timestamp as i64, 0).single() {
+        Some(val) => val.format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+/// Formats a timestamp as a local time to the minute, for tables.
+pub fn format_time_short(timestamp: u64) -> String {
+    match Local.timestamp_opt(timestamp as i64, 0).single() {
+        Some(val) => val.format("%Y-%m-%d %H:%M").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+/// Formats a timestamp as a local date.
+pub fn format_date(timestamp: u64) -> String {
+    match Local.timestamp_opt(timestamp as i64, 0).single() {
+        Some(val) => val.format("%Y-%m-%d").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+/// `$XDG_STATE_HOME/batteryctl`, where the daemon keeps what it records.
+pub fn state_dir() -> PathBuf {
+    let xdg_state = env::var("XDG_STATE_HOME")
+        .or_else(|_| env::var("HOME")
+        .map(|home| format!("{}/.local/state", home)))
+        .unwrap_or_else(|_| ".".to_string());
+
+    PathBuf::from(xdg_state).join("batteryctl")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp: u64) -> Sample {
+        Sample {
+            timestamp,
+            device: "BAT0".to_string(),
+            capacity: Some(87),
+            status: Some(battery::Status::NotCharging),
+            energy_now: Some(41760000),
+            power_now: None,
+            voltage_now: Some(12106000),
+            temp: Some(-45),
+        }
+    }
+
+    #[test]
+    fn samples_round_trip() {
+        let line = sample(1700000000).to_line();
+
+        assert_eq!(line, "1700000000\tBAT0\t87\tNot charging\t41760000\t\t12106000\t-45");
+        assert_eq!(Sample::parse(&line).unwrap(), sample(1700000000));
+        assert!(Sample::parse("1700000000\tBAT0").is_err());
+    }
+
+    #[test]
+    fn parses_timestamps_and_durations() {
+        let now = 1700000000;
+
+        assert_eq!(parse_time("1690000000", now), Ok(1690000000));
+        assert_eq!(parse_time("90m", now), Ok(now - 90 * 60));
+        assert_eq!(parse_time("2d", now), Ok(now - 2 * 24 * 60 * 60));
+        assert!(parse_time("2024-05-01", now).is_ok());
+        assert!(parse_time("2024-05-01T14:30", now).is_ok());
+        assert!(parse_time("yesterday", now).is_err());
+    }
+
+    #[test]
+    fn rotates_when_the_file_outgrows_the_limit() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let history = History::new(dir.path().to_path_buf(), config::History {
+            max_size_kb: 1,
+            max_files: 2,
+            ..config::History::default()
+        });
+
+        // Each batch is well over a kilobyte, so every append after the first rotates.
+        let batch: Vec<Sample> = (0..30).map(sample).collect();
+        for _ in 0..4 {
+            history.append(&batch).unwrap();
+        }
+
+        assert_eq!(history.files(), vec![history.path(2), history.path(1), history.path(0)]);
+        assert_eq!(history.read().unwrap().len(), 90);
+        let contents = fs::read_to_string(history.path(0)).unwrap();
+        assert!(contents.starts_with(HEADER));
+        assert_eq!(contents.lines().count(), 31);
+    }
+
+    #[test]
+    fn rotates_and_prunes_by_age() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let history = History::new(dir.path().to_path_buf(), config::History {
+            max_age_days: 1,
+            ..config::History::default()
+        });
+        let day = 24 * 60 * 60;
+
+        // A small file still rotates once its oldest sample is past the limit.
+        history.append(&[sample(now() - 2 * day)]).unwrap();
+        history.append(&[sample(now())]).unwrap();
+        assert_eq!(history.files(), vec![history.path(1), history.path(0)]);
+
+        // A rotated file goes once it was last written before the limit,
+        // without waiting for another rotation.
+        let rotated = fs::File::options().write(true).open(history.path(1)).unwrap();
+        rotated.set_modified(SystemTime::now() - Duration::from_secs(2 * day)).unwrap();
+        history.append(&[sample(now())]).unwrap();
+        assert_eq!(history.files(), vec![history.path(0)]);
+        assert_eq!(history.read().unwrap().len(), 2);
+    }
+}
@@ -22,117 +22,865 @@ mod daemon;
 mod config;
 mod args;
 mod presets;
+mod netlink;
+mod handler;
+mod notify;
+mod power;
+mod health;
+mod history;
+mod chart;
+mod sessions;
+mod charge;
+mod attribute;
+mod helper;
+mod output;
+mod status;
+mod watch;
+mod bar;
 
 use args::{Args, Operation};
+use output::OutputFormat;
 use clap::Parser;
 use config::Config;
+use std::io::Write;
 
 const VERSION: [u8; 3] = [1, 1, 0];
 
+const HISTORY_CHART_WIDTH: usize = 60;
+const HISTORY_CHART_HEIGHT: usize = 8;
+
 fn main() -> Result<(), String> {
-    let args = Args::parse();
+    let args = match Args::try_parse() {
+        Ok(val) => val,
+        // Usage errors come before the arguments are known, so the output
+        // format is looked up on its own.
+        Err(e) => match args::requested_output(std::env::args()) {
+            Some(format) if format != OutputFormat::Text && e.use_stderr() => {
+                let message = e.to_string();
+                output::print_error(format, message.trim_start_matches("error: ").trim_end());
+                std::process::exit(e.exit_code());
+            }
+            _ => e.exit(),
+        },
+    };
+
+    // The helper runs as a system service, without a user or a config.
+    if let Operation::Helper = args.operation {
+        return operation_helper(&args);
+    }
 
-    let mut config = config::Config::load().unwrap();
+    let result = config::Config::load().and_then(|mut config| run(&args, &mut config));
 
+    // Scripts reading JSON or TOML get the error in the same format.
+    match (result, args.output) {
+        (Err(e), format) if format != OutputFormat::Text => {
+            output::print_error(format, &e);
+            std::process::exit(1);
+        }
+        (result, _) => result,
+    }
+}
+
+fn run(args: &Args, config: &mut Config) -> Result<(), String> {
     match args.operation {
-        Operation::GetProperty => operation_get_property(&args),
-        Operation::ListDevices => operation_list_devices(),
-        Operation::Daemon      => operation_daemon(&config),
-        Operation::ConfigGet   => operation_config_get(&args, &config),
-        Operation::ConfigSet   => operation_config_set(&args, &mut config),
+        Operation::GetProperty => operation_get_property(args),
+        Operation::SetProperty => operation_set_property(args),
+        Operation::ListDevices => operation_list_devices(args),
+        Operation::Status      => operation_status(args),
+        Operation::TimeLeft    => operation_time_left(args),
+        Operation::Health      => operation_health(args, config),
+        Operation::History     => operation_history(args, config),
+        Operation::Sessions    => operation_sessions(args, config),
+        Operation::ChargeLimit => operation_charge_limit(args),
+        Operation::Watch       => operation_watch(args),
+        Operation::Bar         => operation_bar(args, config),
+        Operation::Daemon      => operation_daemon(args, config),
+        Operation::Helper      => unreachable!(),
+        Operation::ConfigGet   => operation_config_get(args, config),
+        Operation::ConfigSet   => operation_config_set(args, config),
         Operation::Version |
-        Operation::V           => operation_version(),
+        Operation::V           => operation_version(args),
     }
 }
 
 fn operation_get_property(args: &Args) -> Result<(), String> {
-    match battery::get_device_property_raw(
-        args.device.as_ref().unwrap(),
-        args.property.as_ref().unwrap()
-    ) {
-        Ok(val) => println!("{}", val),
+    let device = args.device.as_ref().unwrap();
+
+    let props = match battery::Properties::load(&args.sysfs_root, device) {
+        Ok(val) => val,
         Err(e) => return Err(e.to_string()),
     };
 
+    if args.all {
+        let properties = match props.all() {
+            Ok(val) => val,
+            Err(e) => return Err(e.to_string()),
+        };
+        if args.output != OutputFormat::Text {
+            return output::print(args.output, &output::Properties {
+                device: device.clone(),
+                properties,
+            });
+        }
+        properties
+            .iter()
+            .for_each(|(key, val)| println!("{}={}", key, val));
+
+        return Ok(());
+    }
+
+    let property = match args.key.as_ref() {
+        Some(val) => val,
+        None => return Err("A property name or --all is required.".to_string()),
+    };
+
+    let bat = match battery::Battery::from_properties(&props) {
+        Ok(val) => val,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    // Attributes the model doesn't cover are still read straight from the device.
+    let value = match bat.property(property) {
+        Some(val) => val,
+        None => match props.get(property) {
+            Ok(Some(val)) => val,
+            Ok(None) => return Err(battery::BatteryError::MissingProperty(property.clone()).to_string()),
+            Err(e) => return Err(e.to_string()),
+        },
+    };
+
+    if args.output != OutputFormat::Text {
+        return output::print(args.output, &output::Property {
+            device: device.clone(),
+            property: property.clone(),
+            value,
+        });
+    }
+    println!("{}", value);
+
     Ok(())
 }
-fn operation_list_devices() -> Result<(), String> {
-    match battery::get_devices() {
-        Ok(val) =>
-            val
+fn operation_set_property(args: &Args) -> Result<(), String> {
+    let device = args.device.as_ref().unwrap();
+
+    let props = match battery::Properties::load(&args.sysfs_root, device) {
+        Ok(val) => val,
+        Err(e) => return Err(e.to_string()),
+    };
+    let writable = match props.writable() {
+        Ok(val) => val,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    // Without a property, lists what can be set.
+    let property = match args.key.as_ref() {
+        Some(val) => val,
+        None => {
+            if writable.is_empty() {
+                return Err(format!("{} has no writable properties", device));
+            }
+            let properties: Vec<output::WritableProperty> = writable
                 .iter()
-                .for_each(|device| println!("{}", device) ),
+                .map(|name| output::WritableProperty {
+                    name: name.clone(),
+                    value: props.read_attribute(name).ok().flatten(),
+                    accepts: attribute::describe(&props, name),
+                })
+                .collect();
+
+            if args.output != OutputFormat::Text {
+                return output::print(args.output, &output::WritableProperties {
+                    device: device.clone(),
+                    properties,
+                });
+            }
+            let mut rows = vec![["PROPERTY", "VALUE", "ACCEPTS"].map(String::from).to_vec()];
+            for property in properties {
+                rows.push(vec![
+                    property.name,
+                    property.value.unwrap_or("-".to_string()),
+                    property.accepts.unwrap_or("-".to_string()),
+                ]);
+            }
+            print_table(&rows);
+
+            return Ok(());
+        }
+    };
+    let value = match args.value.as_ref() {
+        Some(val) => val,
+        None => return Err("A value to set is required.".to_string()),
+    };
+
+    if !writable.contains(property) {
+        return match props.read_attribute(property) {
+            Ok(Some(_)) => Err(format!("'{}' of {} is read-only", property, device)),
+            Ok(None) => Err(battery::BatteryError::MissingProperty(property.clone()).to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+    }
+    match attribute::validate(&props, property, value) {
+        Ok(true) => (),
+        Ok(false) => eprintln!("[WARN] batteryctl doesn't know which values '{}' accepts, writing it unchecked", property),
+        Err(e) => return Err(e),
+    }
+
+    let report = output::Write {
+        device: device.clone(),
+        property: property.clone(),
+        path: props.path(property).display().to_string(),
+        value: value.clone(),
+        previous: props.read_attribute(property).ok().flatten(),
+        dry_run: args.dry_run,
+    };
+
+    if args.dry_run {
+        if !props.can_write(property) {
+            eprintln!("[WARN] {}", attribute::permission_hint(&props, property));
+        }
+        if args.output != OutputFormat::Text {
+            return output::print(args.output, &report);
+        }
+        println!("Would write '{}' to {} (currently '{}')", value, report.path, report.previous.as_deref().unwrap_or("-"));
+
+        return Ok(());
+    }
+
+    let result = match props.write(property, value) {
+        Ok(_) => Ok(()),
+        Err(battery::BatteryError::PermissionDenied(_)) => match helper::set_property(device, property, value, true) {
+            Ok(_) => Ok(()),
+            Err(helper::HelperError::NotInstalled) => Err(attribute::permission_hint(&props, property)),
+            Err(e) => Err(e.to_string()),
+        },
+        Err(e) => Err(e.to_string()),
+    };
+    match (result, args.output) {
+        (Ok(_), OutputFormat::Text) => Ok(()),
+        (Ok(_), format) => output::print(format, &report),
+        (Err(e), _) => Err(e),
+    }
+}
+fn operation_list_devices(args: &Args) -> Result<(), String> {
+    let devices: Vec<battery::Device> = match battery::load_devices(&args.sysfs_root) {
+        Ok(val) => val
+            .into_iter()
+            .filter(|device| args.device_type.is_none_or(|t| device.device_type == t))
+            .filter(|device| args.scope.is_none_or(|s| device.effective_scope() == s))
+            .collect(),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    if !args.wide && args.output == OutputFormat::Text {
+        devices
+            .iter()
+            .for_each(|device| println!("{}", device.name));
+
+        return Ok(());
+    }
+
+    let mut reports = Vec::new();
+    for device in &devices {
+        match battery::Battery::from_properties(&device.properties) {
+            Ok(val) => reports.push(output::Device::of(device, &val)),
+            Err(e) => eprintln!("[WARN] Skipping {}: {}", device.name, e),
+        };
+    }
+    if args.output != OutputFormat::Text {
+        return output::print(args.output, &output::Devices { devices: reports });
+    }
+
+    let mut rows = vec![[
+        "NAME", "TYPE", "SCOPE", "STATE", "MODEL", "CAPACITY", "STATUS",
+    ].map(String::from).to_vec()];
+    for device in reports {
+        let state = match (device.online, device.present) {
+            (Some(true), _) => "online",
+            (Some(false), _) => "offline",
+            (None, Some(true)) => "present",
+            (None, Some(false)) => "absent",
+            (None, None) => "-",
+        };
+
+        rows.push([
+            device.name,
+            device.device_type,
+            device.scope,
+            state.to_string(),
+            device.model.unwrap_or("-".to_string()),
+            device.capacity.map_or("-".to_string(), |c| format!("{}%", c)),
+            device.status.unwrap_or("-".to_string()),
+        ].to_vec());
+    }
+
+    print_table(&rows);
+
+    Ok(())
+}
+fn operation_status(args: &Args) -> Result<(), String> {
+    let devices = match battery::load_devices(&args.sysfs_root) {
+        Ok(val) => val,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    // Peripherals report their own batteries and chargers, which don't power the system.
+    let system = devices.iter().filter(|d| d.effective_scope() == battery::Scope::System);
+    let adapters: Vec<output::Adapter> = system
+        .clone()
+        .filter(|d| matches!(d.device_type, battery::DeviceType::Mains | battery::DeviceType::Usb))
+        .map(|d| output::Adapter {
+            name: d.name.clone(),
+            device_type: d.device_type.to_string(),
+            online: d.online,
+        })
+        .collect();
+    let mut batteries = Vec::new();
+    for device in system.filter(|d| d.device_type == battery::DeviceType::Battery) {
+        match battery::Battery::from_properties(&device.properties) {
+            Ok(val) => batteries.push(val),
+            Err(e) => eprintln!("[WARN] Skipping {}: {}", device.name, e),
+        }
+    }
+
+    let total = status::Total::of(&batteries);
+    let report = output::Status {
+        on_ac: adapters.iter().any(|a| a.online == Some(true)),
+        adapters,
+        batteries: batteries.iter().map(output::BatteryStatus::of).collect(),
+        total: (!batteries.is_empty()).then(|| output::TotalStatus::from(&total)),
+    };
+    if args.output != OutputFormat::Text {
+        return output::print(args.output, &report);
+    }
+
+    let online: Vec<&str> = report.adapters
+        .iter()
+        .filter(|a| a.online == Some(true))
+        .map(|a| a.name.as_str())
+        .collect();
+    match online.is_empty() {
+        true => println!("On battery power"),
+        false => println!("On AC power ({})", online.join(", ")),
+    }
+    if report.batteries.is_empty() {
+        return Ok(());
+    }
+
+    let unknown = || "-".to_string();
+    let time_left = |to_empty: Option<u64>, to_full: Option<u64>, status: Option<&str>| match (to_empty, to_full, status) {
+        (Some(secs), _, _) => format!("{} to empty", format_duration(std::time::Duration::from_secs(secs))),
+        (_, Some(secs), _) => format!("{} to full", format_duration(std::time::Duration::from_secs(secs))),
+        (_, _, Some("Full")) => "full".to_string(),
+        _ => unknown(),
+    };
+    let energy = |now: Option<f64>, full: Option<f64>| match (now, full) {
+        (Some(now), Some(full)) => format!("{:.1}/{:.1} Wh", now, full),
+        _ => unknown(),
+    };
+    let mut rows = vec![[
+        "BATTERY", "CAPACITY", "STATUS", "POWER", "TIME_LEFT", "ENERGY", "HEALTH",
+    ].map(String::from).to_vec()];
+    for bat in &report.batteries {
+        rows.push([
+            bat.name.clone(),
+            bat.capacity.map_or_else(unknown, |c| format!("{}%", c)),
+            bat.status.clone().unwrap_or_else(unknown),
+            bat.power_w.map_or_else(unknown, |p| format!("{:.2} W", p)),
+            time_left(bat.time_to_empty_secs, bat.time_to_full_secs, bat.status.as_deref()),
+            energy(bat.energy_wh, bat.energy_full_wh),
+            bat.health_percent.map_or_else(unknown, |h| format!("{:.1}%", h)),
+        ].to_vec());
+    }
+    // A total of one battery would just repeat it.
+    if let (Some(total), true) = (&report.total, report.batteries.len() > 1) {
+        rows.push([
+            "total".to_string(),
+            total.capacity_percent.map_or_else(unknown, |c| format!("{:.1}%", c)),
+            total.status.clone().unwrap_or_else(unknown),
+            total.power_w.map_or_else(unknown, |p| format!("{:.2} W", p)),
+            time_left(total.time_to_empty_secs, total.time_to_full_secs, total.status.as_deref()),
+            energy(total.energy_wh, total.energy_full_wh),
+            unknown(),
+        ].to_vec());
+    }
+    print_table(&rows);
+
+    Ok(())
+}
+fn operation_time_left(args: &Args) -> Result<(), String> {
+    let device = args.device.as_ref().unwrap();
+
+    let bat = match battery::Battery::load(&args.sysfs_root, device) {
+        Ok(val) => val,
         Err(e) => return Err(e.to_string()),
     };
 
+    // A single reading has no capacity rate to fall back to, unlike the daemon.
+    let time_left = battery::TimeLeft::of(&bat);
+    if args.output != OutputFormat::Text {
+        return output::print(args.output, &output::TimeLeft {
+            device: device.clone(),
+            status: bat.status.map(|s| s.to_string()),
+            time_to_empty_secs: time_left.to_empty.map(|d| d.as_secs()),
+            time_to_full_secs: time_left.to_full.map(|d| d.as_secs()),
+        });
+    }
+    match (time_left.to_empty, time_left.to_full, bat.status) {
+        (Some(val), _, _) => println!("{} until empty", format_duration(val)),
+        (_, Some(val), _) => println!("{} until full", format_duration(val)),
+        (_, _, Some(battery::Status::Full)) => println!("Full"),
+        _ => println!("Unknown"),
+    }
+
     Ok(())
 }
-fn operation_daemon(config: &Config) -> Result<(), String> {
-    match daemon::start(config) {
+fn operation_health(args: &Args, config: &Config) -> Result<(), String> {
+    let batteries: Vec<battery::Battery> = if args.all {
+        let devices = match battery::load_devices(&args.sysfs_root) {
+            Ok(val) => val,
+            Err(e) => return Err(e.to_string()),
+        };
+        // Mice and keyboards report their batteries too, but don't wear like the system's.
+        let mut batteries = Vec::new();
+        for device in devices
+            .iter()
+            .filter(|d| d.device_type == battery::DeviceType::Battery && d.effective_scope() == battery::Scope::System)
+        {
+            match battery::Battery::from_properties(&device.properties) {
+                Ok(val) => batteries.push(val),
+                Err(e) => eprintln!("[WARN] Skipping {}: {}", device.name, e),
+            }
+        }
+        batteries
+    } else {
+        match battery::Battery::load(&args.sysfs_root, args.device.as_ref().unwrap()) {
+            Ok(val) => vec![val],
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+
+    let replacement_percent = config.health.replacement_percent;
+    let mut reports = Vec::new();
+    for (i, bat) in batteries.iter().enumerate() {
+        let health = health::Health::of(bat);
+        let trend = match args.trend {
+            true => Some(health_trend(bat, config)?),
+            false => None,
+        };
+        if args.output != OutputFormat::Text {
+            let trend = trend.map(|t| t.map(|t| output::Trend::of(&t, replacement_percent)));
+            reports.push(output::Health::of(&health, trend));
+            continue;
+        }
+
+        let unknown = || "-".to_string();
+
+        let voltage = match (health.voltage_v, health.voltage_min_design_v, health.voltage_margin_percent()) {
+            (Some(now), Some(min), Some(margin)) if margin < 0.0 =>
+                format!("{:.2} V, {:.1}% below the {:.2} V design minimum", now, -margin, min),
+            (Some(now), Some(min), Some(margin)) =>
+                format!("{:.2} V, {:.1}% above the {:.2} V design minimum", now, margin, min),
+            (Some(now), _, _) => format!("{:.2} V", now),
+            _ => unknown(),
+        };
+        let mut lines = vec![
+            ("verdict", health.verdict.map_or_else(unknown, |v| v.to_string())),
+            ("health", match health.capacity {
+                Some(c) => format!("{:.1}% ({:.2} of {:.2} {})", health.health_percent().unwrap_or(0.0), c.full, c.design, c.unit),
+                None => unknown(),
+            }),
+            ("wear", health.wear_percent().map_or_else(unknown, |w| format!("{:.1}%", w))),
+            ("cycle_count", health.cycle_count.map_or_else(unknown, |c| c.to_string())),
+            ("technology", health.technology.clone().unwrap_or_else(unknown)),
+            ("manufacture_date", health.manufacture_date.clone().unwrap_or_else(unknown)),
+            ("voltage", voltage),
+        ];
+        if let Some(trend) = trend {
+            lines.extend(trend_lines(trend.as_ref(), replacement_percent));
+        }
+
+        if i > 0 {
+            println!();
+        }
+        println!("{}", health.device);
+        for (key, value) in lines {
+            println!("  {:<18}{}", format!("{}:", key), value);
+        }
+    }
+
+    if args.output != OutputFormat::Text {
+        return output::print(args.output, &output::Healths { batteries: reports });
+    }
+
+    Ok(())
+}
+/// The trend `health --trend` shows, from the full capacity the daemon records daily.
+/// `None` until there are enough records.
+fn health_trend(bat: &battery::Battery, config: &Config) -> Result<Option<health::Trend>, String> {
+    let records: Vec<health::Record> = match health::CapacityLog::open().read() {
+        Ok(val) => val.into_iter().filter(|r| r.device == bat.name).collect(),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    Ok(health::Trend::of(&records, config.health.replacement_percent))
+}
+/// The lines `health --trend` adds.
+fn trend_lines(trend: Option<&health::Trend>, replacement_percent: u8) -> Vec<(&'static str, String)> {
+    let trend = match trend {
+        Some(val) => val,
+        None => return vec![("trend", "not enough data yet, the daemon records the capacity daily".to_string())],
+    };
+
+    let replacement = match trend.replacement {
+        health::Projection::Reached => format!("already below {}% of design", replacement_percent),
+        health::Projection::At(timestamp) => format!("{}% of design around {}", replacement_percent, history::format_date(timestamp)),
+        health::Projection::Never => "not projected, the capacity isn't decreasing".to_string(),
+    };
+    vec![
+        ("trend", format!("{:+.2}% per month over {} days ({} records)", trend.percent_per_month, trend.days, trend.records)),
+        ("per_100_cycles", trend.percent_per_100_cycles.map_or("-".to_string(), |p| format!("{:+.2}%", p))),
+        ("replacement", replacement),
+    ]
+}
+fn operation_history(args: &Args, config: &Config) -> Result<(), String> {
+    let samples = load_history(args, config)?;
+
+    if args.output != OutputFormat::Text {
+        return output::print(args.output, &output::Samples {
+            samples: samples.iter().map(output::sample).collect(),
+        });
+    }
+
+    let opt = |value: Option<String>| value.unwrap_or_default();
+    match args.format {
+        args::HistoryFormat::Csv => {
+            println!("timestamp,time,device,capacity,status,energy_uwh,power_uw,voltage_uv,temp_dc");
+            for sample in &samples {
+                println!(
+                    "{},{},{},{},{},{},{},{},{}",
+                    sample.timestamp,
+                    history::format_time(sample.timestamp),
+                    sample.device,
+                    opt(sample.capacity.map(|v| v.to_string())),
+                    opt(sample.status.map(|v| v.to_string())),
+                    opt(sample.energy_now.map(|v| v.to_string())),
+                    opt(sample.power_now.map(|v| v.to_string())),
+                    opt(sample.voltage_now.map(|v| v.to_string())),
+                    opt(sample.temp.map(|v| v.to_string())),
+                );
+            }
+        }
+        args::HistoryFormat::Jsonl => {
+            for sample in &samples {
+                println!("{}", output::sample(sample));
+            }
+        }
+        format => {
+            if samples.is_empty() {
+                return Err("No samples were recorded in that time range.".to_string());
+            }
+
+            let mut devices: Vec<&String> = samples.iter().map(|sample| &sample.device).collect();
+            devices.sort();
+            devices.dedup();
+            for (i, device) in devices.into_iter().enumerate() {
+                let points: Vec<(u64, f64)> = samples
+                    .iter()
+                    .filter(|sample| &sample.device == device)
+                    .filter_map(|sample| sample.capacity.map(|c| (sample.timestamp, c as f64)))
+                    .collect();
+                let (first, last) = match (points.first(), points.last()) {
+                    (Some(first), Some(last)) => (first.0, last.0),
+                    _ => continue,
+                };
+                let columns = chart::columns(&points, HISTORY_CHART_WIDTH);
+
+                if format == args::HistoryFormat::Sparkline {
+                    println!("{} {} {} {}", device, history::format_time(first), chart::sparkline(&columns), history::format_time(last));
+                    continue;
+                }
+
+                if i > 0 {
+                    println!();
+                }
+                println!("{}: {} samples from {} to {}", device, points.len(), history::format_time(first), history::format_time(last));
+                for line in chart::chart(&columns, HISTORY_CHART_HEIGHT) {
+                    println!("{}", line);
+                }
+                println!("     └{}", "─".repeat(columns.len()));
+            }
+        }
+    }
+
+    Ok(())
+}
+fn operation_sessions(args: &Args, config: &Config) -> Result<(), String> {
+    let samples = load_history(args, config)?;
+    let sessions = sessions::detect(&samples);
+
+    if args.output != OutputFormat::Text {
+        return output::print(args.output, &output::Sessions {
+            sessions: sessions.iter().map(output::Session::from).collect(),
+        });
+    }
+
+    let mut rows = vec![[
+        "DEVICE", "KIND", "START", "DURATION", "CAPACITY", "ENERGY", "AVG_POWER", "PEAK_POWER",
+    ].map(String::from).to_vec()];
+    for session in sessions {
+        let percent = |capacity: Option<u8>| capacity.map_or("-".to_string(), |c| format!("{}%", c));
+        let unit = |value: Option<f64>, unit: &str| value.map_or("-".to_string(), |v| format!("{:.2} {}", v, unit));
+
+        rows.push([
+            session.device.clone(),
+            session.kind.to_string(),
+            history::format_time_short(session.start),
+            format_duration(std::time::Duration::from_secs(session.end - session.start)),
+            format!("{} -> {}", percent(session.start_capacity), percent(session.end_capacity)),
+            unit(session.energy_wh, "Wh"),
+            unit(session.average_power_w, "W"),
+            unit(session.peak_power_w, "W"),
+        ].to_vec());
+    }
+
+    print_table(&rows);
+
+    Ok(())
+}
+/// Reads the recorded samples matching the device and time range arguments.
+fn load_history(args: &Args, config: &Config) -> Result<Vec<history::Sample>, String> {
+    let now = history::now();
+    let since = args.since.as_ref().map(|s| history::parse_time(s, now)).transpose()?;
+    let until = args.until.as_ref().map(|s| history::parse_time(s, now)).transpose()?;
+
+    match history::History::open(config.history.clone()).read() {
+        Ok(val) => Ok(val
+            .into_iter()
+            .filter(|sample| args.all || Some(&sample.device) == args.device.as_ref())
+            .filter(|sample| since.is_none_or(|t| sample.timestamp >= t))
+            .filter(|sample| until.is_none_or(|t| sample.timestamp <= t))
+            .collect()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+fn operation_charge_limit(args: &Args) -> Result<(), String> {
+    let device = args.device.as_ref().unwrap();
+
+    let mut control = match charge::ChargeControl::load(&args.sysfs_root, device) {
+        Ok(val) => val,
+        Err(e) => return Err(e.to_string()),
+    };
+    if !control.is_supported() {
+        return Err(format!("{} doesn't support charge control", device));
+    }
+
+    let limit = config::ChargeLimit {
+        battery: None,
+        start: args.start,
+        end: args.end,
+        behaviour: args.behaviour,
+    };
+    if !limit.is_empty() {
+        control.validate(&limit)?;
+        control.apply(&limit, true)?;
+        // Read back what the driver actually settled on.
+        control = match charge::ChargeControl::load(&args.sysfs_root, device) {
+            Ok(val) => val,
+            Err(e) => return Err(e.to_string()),
+        };
+    }
+
+    if args.output != OutputFormat::Text {
+        return output::print(args.output, &output::ChargeLimit {
+            device: device.clone(),
+            start_threshold: control.start,
+            end_threshold: control.end,
+            behaviour: control.behaviour,
+            available_behaviours: control.available,
+        });
+    }
+
+    let unknown = || "-".to_string();
+    let behaviour = match (control.behaviour, &control.available) {
+        (behaviour, Some(available)) => {
+            let available: Vec<String> = available.iter().map(|b| b.to_string()).collect();
+            format!("{} (available: {})", behaviour.map_or_else(unknown, |b| b.to_string()), available.join(", "))
+        }
+        (Some(behaviour), None) => behaviour.to_string(),
+        (None, None) => unknown(),
+    };
+    let lines = [
+        ("start_threshold", control.start.map_or_else(unknown, |s| format!("{}%", s))),
+        ("end_threshold", control.end.map_or_else(unknown, |e| format!("{}%", e))),
+        ("behaviour", behaviour),
+    ];
+
+    println!("{}", device);
+    for (key, value) in lines {
+        println!("  {:<18}{}", format!("{}:", key), value);
+    }
+
+    Ok(())
+}
+fn operation_watch(args: &Args) -> Result<(), String> {
+    let device = args.device.as_ref().unwrap();
+    if args.output == OutputFormat::Toml {
+        return Err("watch prints a JSON object per line; use --output json".to_string());
+    }
+
+    let interval = std::time::Duration::from_millis(args.interval);
+    watch::run(&args.sysfs_root, device, &args.properties, interval, |changes| {
+        let mut stdout = std::io::stdout().lock();
+        for change in changes {
+            if args.output != OutputFormat::Text {
+                writeln!(stdout, "{}", output::change(change))?;
+                continue;
+            }
+            writeln!(
+                stdout,
+                "{} {} {}={}",
+                history::format_time(change.timestamp),
+                change.device,
+                change.property,
+                change.value.as_deref().unwrap_or("-"),
+            )?;
+        }
+        Ok(())
+    })
+}
+fn operation_bar(args: &Args, config: &Config) -> Result<(), String> {
+    let device = args.device.as_ref().unwrap();
+    if args.output != OutputFormat::Text {
+        return Err("bar prints the format of the status bar; use --bar-format".to_string());
+    }
+
+    // Drivers without a power draw still get a time left from the capacity over time.
+    let mut estimator = battery::Estimator::default();
+    let mut render = || -> Result<String, String> {
+        let bat = battery::Battery::load(&args.sysfs_root, device).map_err(|e| e.to_string())?;
+        let time_left = estimator.update(&bat, std::time::Instant::now());
+        Ok(bar::Module::of(&bat, config, time_left).render(args.bar_format, args.continuous))
+    };
+
+    if !args.continuous {
+        println!("{}", render()?);
+        return Ok(());
+    }
+
+    let properties = ["capacity", "status", "power_now", "current_now", "energy_now", "charge_now"].map(String::from);
+    let interval = std::time::Duration::from_millis(args.interval);
+    let mut last = None;
+    watch::run(&args.sysfs_root, device, &properties, interval, |_| {
+        let module = match render() {
+            Ok(val) => val,
+            Err(e) => {
+                eprintln!("[ERROR] Reading {} failed: {}", device, e);
+                return Ok(());
+            }
+        };
+        // The time left changes with every read of the power draw, but it's
+        // only shown to the minute.
+        if last.as_ref() != Some(&module) {
+            writeln!(std::io::stdout(), "{}", module)?;
+            last = Some(module);
+        }
+        Ok(())
+    })
+}
+fn operation_daemon(args: &Args, config: &Config) -> Result<(), String> {
+    match daemon::start(config, &args.sysfs_root) {
         Ok(_) => Ok(()),
         Err(e) => Err(e.to_string()),
     }
 }
+fn operation_helper(args: &Args) -> Result<(), String> {
+    helper::serve(&args.sysfs_root).map_err(|e| e.to_string())
+}
 fn operation_config_get(args: &Args, config: &Config) -> Result<(), String> {
-    match args.key.as_ref().unwrap() {
+    let key = args.config_key()?;
+    if args.output != OutputFormat::Text {
+        return output::print(args.output, &config_value(config, &key)?);
+    }
+
+    match key {
         args::ConfigKey::Batteries => println!("{:?}", config.batteries),
         args::ConfigKey::ReadDelay => println!("{}", config.read_delay_ms),
-        args::ConfigKey::GoodCapacity => println!("{}", config.good_capacity),
-        args::ConfigKey::OkayCapacity => println!("{}", config.okay_capacity),
-        args::ConfigKey::BadCapacity => println!("{}", config.bad_capacity),
-        args::ConfigKey::CriticalCapacity => println!("{}", config.critical_capacity),
-        args::ConfigKey::GoodCapacityHandler => println!("{:?}", config.good_capacity_handler),
-        args::ConfigKey::OkayCapacityHandler => println!("{:?}", config.okay_capacity_handler),
-        args::ConfigKey::BadCapacityHandler => println!("{:?}", config.bad_capacity_handler),
-        args::ConfigKey::CriticalCapacityHandler => println!("{:?}", config.critical_capacity_handler),
+        args::ConfigKey::Hysteresis => println!("{}", config.hysteresis),
+        args::ConfigKey::Thresholds => print!("{}", config.thresholds_toml()),
+        args::ConfigKey::AcConnectedHandler => println!("{:?}", config.ac_connected_handler),
+        args::ConfigKey::AcDisconnectedHandler => println!("{:?}", config.ac_disconnected_handler),
+        args::ConfigKey::ChargingStartedHandler => println!("{:?}", config.charging_started_handler),
+        args::ConfigKey::DischargingStartedHandler => println!("{:?}", config.discharging_started_handler),
+        args::ConfigKey::FullHandler => println!("{:?}", config.full_handler),
     }
 
     Ok(())
 }
 fn operation_config_set(args: &Args, config: &mut Config) -> Result<(), String> {
-    match args.key.as_ref().unwrap() {
-        args::ConfigKey::Batteries => eprintln!("[Error] Setting array properties via the CLI isn't supported yet. Edit the configuration file at '~/.config/batteryctl/ instead.'"),
-        args::ConfigKey::ReadDelay => config.read_delay_ms = args.value.clone()
-            .unwrap()
-            .parse::<u64>()
-            .expect("[Error] The value has to be a valid unsigned 64-bit integer."),
-        args::ConfigKey::GoodCapacity => config.good_capacity = args.value.clone()
-            .unwrap()
-            .parse::<u8>()
-            .expect("[Error] The value has to be a valid unsigned 8-bit integer."),
-        args::ConfigKey::OkayCapacity => config.okay_capacity = args.value.clone()
-            .unwrap()
-            .parse::<u8>()
-            .expect("[Error] The value has to be a valid unsigned 8-bit integer."),
-        args::ConfigKey::BadCapacity => config.bad_capacity = args.value.clone()
-            .unwrap()
-            .parse::<u8>()
-            .expect("[Error] The value has to be a valid unsigned 8-bit integer."),
-        args::ConfigKey::CriticalCapacity => config.critical_capacity = args.value.clone()
-            .unwrap()
-            .parse::<u8>()
-            .expect("[Error] The value has to be a valid unsigned 8-bit integer."),
-        args::ConfigKey::GoodCapacityHandler =>
-            config.good_capacity_handler = Some(args.value.clone().unwrap()),
-        args::ConfigKey::OkayCapacityHandler =>
-            config.okay_capacity_handler = Some(args.value.clone().unwrap()),
-        args::ConfigKey::BadCapacityHandler =>
-            config.bad_capacity_handler = Some(args.value.clone().unwrap()),
-        args::ConfigKey::CriticalCapacityHandler =>
-            config.critical_capacity_handler = Some(args.value.clone().unwrap()),
+    let key = args.config_key()?;
+    match key {
+        args::ConfigKey::Batteries |
+        args::ConfigKey::Thresholds => return Err("Setting array properties via the CLI isn't supported yet. Edit the configuration file at '~/.config/batteryctl/' instead.".to_string()),
+        args::ConfigKey::ReadDelay => config.read_delay_ms = match args.value.as_ref().unwrap().parse::<u64>() {
+            Ok(val) => val,
+            Err(_) => return Err("The value has to be a valid unsigned 64-bit integer.".to_string()),
+        },
+        args::ConfigKey::Hysteresis => config.hysteresis = match args.value.as_ref().unwrap().parse::<u8>() {
+            Ok(val) => val,
+            Err(_) => return Err("The value has to be a valid unsigned 8-bit integer.".to_string()),
+        },
+        args::ConfigKey::AcConnectedHandler =>
+            config.ac_connected_handler = Some(config::Handler::Shell(args.value.clone().unwrap())),
+        args::ConfigKey::AcDisconnectedHandler =>
+            config.ac_disconnected_handler = Some(config::Handler::Shell(args.value.clone().unwrap())),
+        args::ConfigKey::ChargingStartedHandler =>
+            config.charging_started_handler = Some(config::Handler::Shell(args.value.clone().unwrap())),
+        args::ConfigKey::DischargingStartedHandler =>
+            config.discharging_started_handler = Some(config::Handler::Shell(args.value.clone().unwrap())),
+        args::ConfigKey::FullHandler =>
+            config.full_handler = Some(config::Handler::Shell(args.value.clone().unwrap())),
     };
 
     config.save();
 
+    if args.output != OutputFormat::Text {
+        return output::print(args.output, &config_value(config, &key)?);
+    }
+
     Ok(())
 }
-fn operation_version() -> Result<(), String> {
-    println!(
-        "batteryctl v{}.{}.{}",
-        VERSION[0],
-        VERSION[1],
-        VERSION[2],
-    );
+/// A config key and its value, as `config-get` and `config-set` print them with `--output`.
+fn config_value(config: &Config, key: &args::ConfigKey) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    let config = serde_json::to_value(config).map_err(|e| e.to_string())?;
+    let value = config.get(key.field()).cloned().unwrap_or(serde_json::Value::Null);
+
+    Ok(serde_json::Map::from_iter([(key.field().to_string(), value)]))
+}
+/// Prints rows as columns padded to their widest cell.
+fn print_table(rows: &[Vec<String>]) {
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|i| rows.iter().map(|row| row[i].chars().count()).max().unwrap_or(0))
+        .collect();
+    for row in rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+fn format_duration(duration: std::time::Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+fn operation_version(args: &Args) -> Result<(), String> {
+    let version = format!("{}.{}.{}", VERSION[0], VERSION[1], VERSION[2]);
+
+    if args.output != OutputFormat::Text {
+        return output::print(args.output, &output::Version { version });
+    }
+    println!("batteryctl v{}", version);
 
     Ok(())
 }
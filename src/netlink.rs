@@ -0,0 +1,237 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::{
+    collections::HashMap,
+    io,
+    mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// The multicast group the kernel broadcasts its uevents on.
+const KERNEL_UEVENT_GROUP: u32 = 1;
+
+/// A kernel uevent received over netlink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Uevent {
+    pub action: String,
+    pub devpath: String,
+    pub subsystem: Option<String>,
+    pub properties: HashMap<String, String>,
+}
+
+impl Uevent {
+    /// Parses a kernel uevent message, a `<action>@<devpath>` header followed
+    /// by NUL-separated `KEY=value` pairs.
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        let mut fields = buf
+            .split(|b| *b == 0)
+            .filter(|field| !field.is_empty())
+            .map(String::from_utf8_lossy);
+
+        let header = fields.next()?;
+        let (action, devpath) = header.split_once('@')?;
+
+        let properties: HashMap<String, String> = fields
+            .filter_map(|field| field
+                .split_once('=')
+                .map(|(k, v)| (k.to_string(), v.to_string())))
+            .collect();
+
+        Some(Self {
+            action: action.to_string(),
+            devpath: devpath.to_string(),
+            subsystem: properties.get("SUBSYSTEM").cloned(),
+            properties,
+        })
+    }
+
+    pub fn is_power_supply(&self) -> bool {
+        self.subsystem.as_deref() == Some("power_supply")
+    }
+
+    /// The name of the power_supply device the event is about.
+    pub fn device_name(&self) -> Option<&str> {
+        self.properties
+            .get("POWER_SUPPLY_NAME")
+            .map(String::as_str)
+            .or_else(|| self.devpath.rsplit('/').next())
+    }
+}
+
+/// A `NETLINK_KOBJECT_UEVENT` socket subscribed to the kernel's uevents.
+pub struct Listener {
+    fd: OwnedFd,
+}
+
+impl Listener {
+    pub fn open() -> io::Result<Self> {
+        let raw = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                libc::NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        if raw < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = KERNEL_UEVENT_GROUP;
+
+        let ret = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { fd })
+    }
+
+    /// Waits up to `timeout` for the next uevent, returning `None` on timeout.
+    pub fn recv_timeout(&self, timeout: Duration) -> io::Result<Option<Uevent>> {
+        let mut pfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+
+        let ret = unsafe { libc::poll(&mut pfd, 1, timeout_ms) };
+        if ret < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                return Ok(None);
+            }
+            return Err(e);
+        }
+        if ret == 0 {
+            return Ok(None);
+        }
+
+        let mut buf = [0u8; 8192];
+        let mut sender: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        let mut sender_len = mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t;
+        let len = unsafe {
+            libc::recvfrom(
+                self.fd.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+                &mut sender as *mut libc::sockaddr_nl as *mut libc::sockaddr,
+                &mut sender_len,
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Only the kernel (port id 0) is trusted to send uevents.
+        if sender.nl_pid != 0 {
+            return Ok(None);
+        }
+
+        Ok(Uevent::parse(&buf[..len as usize]))
+    }
+}
+
+/// Blocks until a power_supply uevent that `wanted` accepts arrives, returning
+/// it, or until `timeout` passes. Without a listener, it just sleeps.
+pub fn wait_for_power_supply(
+    listener: Option<&Listener>,
+    timeout: Duration,
+    wanted: impl Fn(&Uevent) -> bool,
+) -> Option<Uevent> {
+    let listener = match listener {
+        Some(val) => val,
+        None => {
+            sleep(timeout);
+            return None;
+        }
+    };
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return None;
+        }
+
+        match listener.recv_timeout(remaining) {
+            Ok(Some(event)) if event.is_power_supply() && wanted(&event) => return Some(event),
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("[ERROR] Reading uevents failed: {e}");
+                sleep(remaining);
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_power_supply_change_events() {
+        let msg = b"change@/devices/LNXSYSTM:00/LNXSYBUS:00/PNP0C0A:00/power_supply/BAT0\0\
+            ACTION=change\0\
+            DEVPATH=/devices/LNXSYSTM:00/LNXSYBUS:00/PNP0C0A:00/power_supply/BAT0\0\
+            SUBSYSTEM=power_supply\0\
+            POWER_SUPPLY_NAME=BAT0\0\
+            POWER_SUPPLY_STATUS=Discharging\0\
+            POWER_SUPPLY_CAPACITY=41\0\
+            SEQNUM=4242\0";
+
+        let event = Uevent::parse(msg).unwrap();
+
+        assert_eq!(event.action, "change");
+        assert!(event.is_power_supply());
+        assert_eq!(event.device_name(), Some("BAT0"));
+        assert_eq!(event.properties["POWER_SUPPLY_CAPACITY"], "41");
+    }
+
+    #[test]
+    fn falls_back_to_the_devpath_for_the_device_name() {
+        let msg = b"add@/devices/platform/USBC000:00/power_supply/ucsi-source-psy-USBC000:001\0\
+            SUBSYSTEM=power_supply\0";
+
+        let event = Uevent::parse(msg).unwrap();
+
+        assert_eq!(event.action, "add");
+        assert_eq!(event.device_name(), Some("ucsi-source-psy-USBC000:001"));
+    }
+
+    #[test]
+    fn rejects_messages_without_a_header() {
+        assert_eq!(Uevent::parse(b"ACTION=change\0"), None);
+        assert_eq!(Uevent::parse(b""), None);
+    }
+}
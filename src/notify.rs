@@ -0,0 +1,142 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::{collections::HashMap, fmt};
+
+use serde::{Deserialize, Serialize};
+use zbus::zvariant::Value;
+
+const NOTIFICATIONS_NAME: &str = "org.freedesktop.Notifications";
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+
+#[derive(Debug)]
+pub enum NotifyError {
+    DBus(String),
+}
+
+impl std::error::Error for NotifyError { }
+
+impl fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::DBus(e) => write!(f, "D-Bus error: {}", e),
+        }
+    }
+}
+
+impl From<zbus::Error> for NotifyError {
+    fn from(e: zbus::Error) -> Self {
+        Self::DBus(e.to_string())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    /// The value of the `urgency` hint in the notification spec.
+    fn hint(&self) -> u8 {
+        match self {
+            Urgency::Low => 0,
+            Urgency::Normal => 1,
+            Urgency::Critical => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub app_name: String,
+    pub summary: String,
+    pub body: String,
+    pub icon: String,
+    pub urgency: Urgency,
+    /// How long the notification stays up in milliseconds, `-1` for the server's default.
+    pub timeout_ms: i32,
+}
+
+/// A client of the freedesktop notification server on the session bus.
+pub struct Notifier {
+    conn: zbus::blocking::Connection,
+}
+
+impl Notifier {
+    pub fn connect() -> Result<Self, NotifyError> {
+        Ok(Self {
+            conn: zbus::blocking::Connection::session()?,
+        })
+    }
+
+    /// Shows a notification, replacing the one with `replaces_id` if it's still
+    /// open. Returns the id of the new notification.
+    pub fn notify(&self, notification: &Notification, replaces_id: u32) -> Result<u32, NotifyError> {
+        let actions: Vec<&str> = Vec::new();
+        let mut hints: HashMap<&str, Value> = HashMap::new();
+        hints.insert("urgency", Value::U8(notification.urgency.hint()));
+
+        let reply = self.conn.call_method(
+            Some(NOTIFICATIONS_NAME),
+            NOTIFICATIONS_PATH,
+            Some(NOTIFICATIONS_NAME),
+            "Notify",
+            &(
+                notification.app_name.as_str(),
+                replaces_id,
+                notification.icon.as_str(),
+                notification.summary.as_str(),
+                notification.body.as_str(),
+                actions,
+                hints,
+                notification.timeout_ms,
+            ),
+        )?;
+
+        Ok(reply.body().deserialize::<u32>()?)
+    }
+}
+
+/// The freedesktop icon name for a charge level.
+pub fn battery_icon(capacity: Option<u8>, charging: bool) -> String {
+    let level = match capacity {
+        Some(80..) => "full",
+        Some(40..) => "good",
+        Some(20..) => "low",
+        Some(10..) => "caution",
+        Some(_) => "empty",
+        None => return "battery-missing".to_string(),
+    };
+
+    if charging {
+        format!("battery-{}-charging", level)
+    } else {
+        format!("battery-{}", level)
+    }
+}
+
+/// Fills `{name}` placeholders in a template. Unknown placeholders are left as they are.
+pub fn render(template: &str, vars: &[(String, String)]) -> String {
+    vars.iter().fold(template.to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{}}}", name.to_lowercase()), value)
+    })
+}
@@ -0,0 +1,414 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! The structures commands print with `--output json` or `--output toml`.
+//! Their fields are part of the CLI, so renaming one breaks scripts.
+
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::battery;
+use crate::charge::ChargeBehaviour;
+use crate::health;
+use crate::history;
+use crate::sessions;
+use crate::status;
+use crate::watch;
+
+/// How commands print their results.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Toml,
+}
+
+/// Prints a result as JSON or TOML. Text is printed by each command itself.
+pub fn print<T: Serialize>(format: OutputFormat, report: &T) -> Result<(), String> {
+    let value = serde_json::to_value(report).map_err(|e| e.to_string())?;
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?),
+        // TOML has no null, so unknown values are left out instead.
+        OutputFormat::Toml => print!("{}", toml::to_string_pretty(&without_nulls(value)).map_err(|e| e.to_string())?),
+        OutputFormat::Text => unreachable!("text output is printed by each command"),
+    }
+
+    Ok(())
+}
+
+/// Prints an error in place of the result.
+pub fn print_error(format: OutputFormat, message: &str) {
+    let error = Error { error: message.to_string() };
+    if let Err(e) = print(format, &error) {
+        eprintln!("[ERROR] {}: {}", e, message);
+    }
+}
+
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(map
+            .into_iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| (k, without_nulls(v)))
+            .collect()),
+        Value::Array(values) => Value::Array(values
+            .into_iter()
+            .filter(|v| !v.is_null())
+            .map(without_nulls)
+            .collect()),
+        value => value,
+    }
+}
+
+#[derive(Serialize)]
+struct Error {
+    error: String,
+}
+
+#[derive(Serialize)]
+pub struct Property {
+    pub device: String,
+    pub property: String,
+    pub value: String,
+}
+
+#[derive(Serialize)]
+pub struct Properties {
+    pub device: String,
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+pub struct Devices {
+    pub devices: Vec<Device>,
+}
+
+#[derive(Serialize)]
+pub struct Device {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub device_type: String,
+    pub scope: String,
+    pub online: Option<bool>,
+    pub present: Option<bool>,
+    pub model: Option<String>,
+    pub capacity: Option<u8>,
+    pub status: Option<String>,
+}
+
+impl Device {
+    pub fn of(device: &battery::Device, bat: &battery::Battery) -> Self {
+        Self {
+            name: device.name.clone(),
+            device_type: device.device_type.to_string(),
+            scope: device.effective_scope().to_string(),
+            online: device.online,
+            present: device.present,
+            model: bat.model_name.clone(),
+            capacity: bat.capacity,
+            status: bat.status.map(|s| s.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TimeLeft {
+    pub device: String,
+    pub status: Option<String>,
+    pub time_to_empty_secs: Option<u64>,
+    pub time_to_full_secs: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct Status {
+    /// Whether any adapter is online.
+    pub on_ac: bool,
+    pub adapters: Vec<Adapter>,
+    pub batteries: Vec<BatteryStatus>,
+    /// All the batteries combined, `null` without any.
+    pub total: Option<TotalStatus>,
+}
+
+#[derive(Serialize)]
+pub struct Adapter {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub device_type: String,
+    pub online: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct BatteryStatus {
+    pub name: String,
+    pub capacity: Option<u8>,
+    pub status: Option<String>,
+    pub power_w: Option<f64>,
+    pub energy_wh: Option<f64>,
+    pub energy_full_wh: Option<f64>,
+    pub time_to_empty_secs: Option<u64>,
+    pub time_to_full_secs: Option<u64>,
+    pub health_percent: Option<f64>,
+}
+
+impl BatteryStatus {
+    pub fn of(bat: &battery::Battery) -> Self {
+        let time_left = battery::TimeLeft::of(bat);
+
+        Self {
+            name: bat.name.clone(),
+            capacity: bat.capacity,
+            status: bat.status.map(|s| s.to_string()),
+            power_w: bat.power_w(),
+            energy_wh: bat.energy_wh(),
+            energy_full_wh: bat.energy_full_wh(),
+            time_to_empty_secs: time_left.to_empty.map(|d| d.as_secs()),
+            time_to_full_secs: time_left.to_full.map(|d| d.as_secs()),
+            health_percent: health::Health::of(bat).health_percent(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TotalStatus {
+    /// Weighted by the energy of each battery.
+    pub capacity_percent: Option<f64>,
+    pub status: Option<String>,
+    pub power_w: Option<f64>,
+    pub energy_wh: Option<f64>,
+    pub energy_full_wh: Option<f64>,
+    pub time_to_empty_secs: Option<u64>,
+    pub time_to_full_secs: Option<u64>,
+}
+
+impl From<&status::Total> for TotalStatus {
+    fn from(total: &status::Total) -> Self {
+        Self {
+            capacity_percent: total.capacity_percent,
+            status: total.status.map(|s| s.to_string()),
+            power_w: total.power_w,
+            energy_wh: total.energy_wh,
+            energy_full_wh: total.energy_full_wh,
+            time_to_empty_secs: total.time_to_empty.map(|d| d.as_secs()),
+            time_to_full_secs: total.time_to_full.map(|d| d.as_secs()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Healths {
+    pub batteries: Vec<Health>,
+}
+
+#[derive(Serialize)]
+pub struct Health {
+    pub device: String,
+    pub verdict: Option<String>,
+    pub health_percent: Option<f64>,
+    pub wear_percent: Option<f64>,
+    pub full_capacity: Option<f64>,
+    pub design_capacity: Option<f64>,
+    /// `Wh` or `Ah`, whichever the driver reports.
+    pub capacity_unit: Option<String>,
+    pub cycle_count: Option<u32>,
+    pub technology: Option<String>,
+    pub manufacture_date: Option<String>,
+    pub voltage_v: Option<f64>,
+    pub voltage_min_design_v: Option<f64>,
+    pub voltage_margin_percent: Option<f64>,
+    /// Only with `--trend`, and `null` until there are enough records.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trend: Option<Option<Trend>>,
+}
+
+impl Health {
+    pub fn of(health: &health::Health, trend: Option<Option<Trend>>) -> Self {
+        Self {
+            device: health.device.clone(),
+            verdict: health.verdict.map(|v| v.to_string()),
+            health_percent: health.health_percent(),
+            wear_percent: health.wear_percent(),
+            full_capacity: health.capacity.map(|c| c.full),
+            design_capacity: health.capacity.map(|c| c.design),
+            capacity_unit: health.capacity.map(|c| c.unit.to_string()),
+            cycle_count: health.cycle_count,
+            technology: health.technology.clone(),
+            manufacture_date: health.manufacture_date.clone(),
+            voltage_v: health.voltage_v,
+            voltage_min_design_v: health.voltage_min_design_v,
+            voltage_margin_percent: health.voltage_margin_percent(),
+            trend,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Trend {
+    pub days: u64,
+    pub records: usize,
+    pub percent_per_month: f64,
+    pub percent_per_100_cycles: Option<f64>,
+    pub replacement_percent: u8,
+    /// `reached`, `projected` or `never`.
+    pub replacement: String,
+    pub replacement_timestamp: Option<u64>,
+}
+
+impl Trend {
+    pub fn of(trend: &health::Trend, replacement_percent: u8) -> Self {
+        let (replacement, replacement_timestamp) = match trend.replacement {
+            health::Projection::Reached => ("reached", None),
+            health::Projection::At(timestamp) => ("projected", Some(timestamp)),
+            health::Projection::Never => ("never", None),
+        };
+
+        Self {
+            days: trend.days,
+            records: trend.records,
+            percent_per_month: trend.percent_per_month,
+            percent_per_100_cycles: trend.percent_per_100_cycles,
+            replacement_percent,
+            replacement: replacement.to_string(),
+            replacement_timestamp,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Samples {
+    pub samples: Vec<Value>,
+}
+
+/// A history sample, as `history --format jsonl` prints it.
+pub fn sample(sample: &history::Sample) -> Value {
+    serde_json::json!({
+        "timestamp": sample.timestamp,
+        "time": history::format_time(sample.timestamp),
+        "device": sample.device,
+        "capacity": sample.capacity,
+        "status": sample.status.map(|v| v.to_string()),
+        "energy_uwh": sample.energy_now,
+        "power_uw": sample.power_now,
+        "voltage_uv": sample.voltage_now,
+        "temp_dc": sample.temp,
+    })
+}
+
+/// A changed property, as `watch --output json` prints it.
+pub fn change(change: &watch::Change) -> Value {
+    serde_json::json!({
+        "timestamp": change.timestamp,
+        "time": history::format_time(change.timestamp),
+        "device": change.device,
+        "property": change.property,
+        "value": change.value,
+        "previous": change.previous,
+    })
+}
+
+#[derive(Serialize)]
+pub struct Sessions {
+    pub sessions: Vec<Session>,
+}
+
+#[derive(Serialize)]
+pub struct Session {
+    pub device: String,
+    /// `charge` or `discharge`.
+    pub kind: String,
+    pub start: u64,
+    pub end: u64,
+    pub duration_secs: u64,
+    pub start_capacity: Option<u8>,
+    pub end_capacity: Option<u8>,
+    pub energy_wh: Option<f64>,
+    pub average_power_w: Option<f64>,
+    pub peak_power_w: Option<f64>,
+}
+
+impl From<&sessions::Session> for Session {
+    fn from(session: &sessions::Session) -> Self {
+        Self {
+            device: session.device.clone(),
+            kind: session.kind.to_string(),
+            start: session.start,
+            end: session.end,
+            duration_secs: session.end - session.start,
+            start_capacity: session.start_capacity,
+            end_capacity: session.end_capacity,
+            energy_wh: session.energy_wh,
+            average_power_w: session.average_power_w,
+            peak_power_w: session.peak_power_w,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ChargeLimit {
+    pub device: String,
+    pub start_threshold: Option<u8>,
+    pub end_threshold: Option<u8>,
+    pub behaviour: Option<ChargeBehaviour>,
+    /// `null` when the driver doesn't list them.
+    pub available_behaviours: Option<Vec<ChargeBehaviour>>,
+}
+
+#[derive(Serialize)]
+pub struct WritableProperties {
+    pub device: String,
+    pub properties: Vec<WritableProperty>,
+}
+
+#[derive(Serialize)]
+pub struct WritableProperty {
+    pub name: String,
+    pub value: Option<String>,
+    /// What batteryctl checks values against, `null` if it doesn't know.
+    pub accepts: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Write {
+    pub device: String,
+    pub property: String,
+    pub path: String,
+    pub value: String,
+    pub previous: Option<String>,
+    pub dry_run: bool,
+}
+
+#[derive(Serialize)]
+pub struct Version {
+    pub version: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_leaves_out_unknown_values() {
+        let value = serde_json::json!({ "a": null, "b": [1, null], "c": { "d": null, "e": "x" } });
+
+        assert_eq!(without_nulls(value), serde_json::json!({ "b": [1], "c": { "e": "x" } }));
+    }
+}
@@ -0,0 +1,127 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::{env, fmt, fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+const LOGIND_NAME: &str = "org.freedesktop.login1";
+const LOGIND_PATH: &str = "/org/freedesktop/login1";
+const LOGIND_MANAGER: &str = "org.freedesktop.login1.Manager";
+
+/// Overrides `/sys/power`, the fallback used without logind.
+pub const SYS_POWER_ENV: &str = "BATTERYCTL_SYS_POWER";
+const SYS_POWER_DIRECTORY: &str = "/sys/power";
+
+#[derive(Debug)]
+pub enum PowerError {
+    Unsupported(String),
+    IOError(String),
+}
+
+impl std::error::Error for PowerError { }
+
+impl fmt::Display for PowerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unsupported(e) => write!(f, "Unsupported: {}", e),
+            Self::IOError(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+/// What the system does when a threshold with an action fires.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Suspend,
+    Hibernate,
+    HybridSleep,
+    PowerOff,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Action::Suspend => "suspend",
+            Action::Hibernate => "hibernate",
+            Action::HybridSleep => "hybrid-sleep",
+            Action::PowerOff => "power-off",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Action {
+    /// The `org.freedesktop.login1.Manager` method performing the action.
+    fn logind_method(&self) -> &'static str {
+        match self {
+            Action::Suspend => "Suspend",
+            Action::Hibernate => "Hibernate",
+            Action::HybridSleep => "HybridSleep",
+            Action::PowerOff => "PowerOff",
+        }
+    }
+
+    /// Asks logind to perform the action, writing to `/sys/power` directly if
+    /// logind isn't available. The writes need root, so the fallback only
+    /// works for system-wide daemons.
+    pub fn perform(&self) -> Result<(), PowerError> {
+        let error = match self.call_logind() {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+        eprintln!("[WARN] logind can't {}, writing to /sys/power instead: {}", self, error);
+
+        self.write_sys_power()
+    }
+
+    fn call_logind(&self) -> zbus::Result<()> {
+        let conn = zbus::blocking::Connection::system()?;
+        // Not interactive, since nobody may be around to answer a polkit prompt.
+        conn.call_method(
+            Some(LOGIND_NAME),
+            LOGIND_PATH,
+            Some(LOGIND_MANAGER),
+            self.logind_method(),
+            &(false,),
+        )?;
+
+        Ok(())
+    }
+
+    fn write_sys_power(&self) -> Result<(), PowerError> {
+        let dir = env::var_os(SYS_POWER_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(SYS_POWER_DIRECTORY));
+        let write = |name: &str, value: &str| fs::write(dir.join(name), value)
+            .map_err(|e| PowerError::IOError(format!("{}: {}", dir.join(name).display(), e)));
+
+        match self {
+            Action::Suspend => write("state", "mem"),
+            Action::Hibernate => write("state", "disk"),
+            Action::HybridSleep => {
+                // Writes the image, then suspends instead of powering off.
+                write("disk", "suspend")?;
+                write("state", "disk")
+            }
+            Action::PowerOff => Err(PowerError::Unsupported("powering off needs logind".to_string())),
+        }
+    }
+}
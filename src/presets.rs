@@ -6,16 +6,103 @@ batteries = [
 ]
 
 # The delay between battery config reads. Too low values can affect battery ife and/or performance.
+# The daemon also reads the batteries whenever the kernel reports a power supply change.
 read_delay_ms = 180000
 
-good_capacity = 75
-okay_capacity = 50
-bad_capacity = 20
-critical_capacity = 5
+# Each threshold handler runs once when the capacity crosses its percentage, and again only
+# after the capacity has moved back more than this many percent past it.
+hysteresis = 2
 
-# good_capacity_handler = "~/.config/batteryctl/scripts/good_capacity.sh"
-# okay_capacity_handler = "~/.config/batteryctl/scripts/okay_capacity.sh"
-# bad_capacity_handler = "~/.config/batteryctl/scripts/bad_capacity.sh"
-# critical_capacity_handler = "~/.config/batteryctl/scripts/critical_capacity.sh"
-"##;
+# How many seconds to wait before a threshold's action, cancelled if power gets connected meanwhile.
+action_countdown_secs = 60
+
+# Handlers get the device name as their last argument and can be written as:
+#   "~/script.sh --flag"                        A shell command line, run with /bin/sh.
+#   ["~/script.sh", "--flag"]                   A program and its arguments, run without a shell.
+#   { command = ["~/script.sh"], timeout = 10 }  Either form with a timeout in seconds, or
+#   { shell = "~/script.sh", timeout = 10 }      they're killed after 60 seconds.
+
+# These run when the charging state changes.
+# ac_connected_handler = "~/.config/batteryctl/scripts/ac_connected.sh"
+# ac_disconnected_handler = "~/.config/batteryctl/scripts/ac_disconnected.sh"
+# charging_started_handler = "~/.config/batteryctl/scripts/charging_started.sh"
+# discharging_started_handler = "~/.config/batteryctl/scripts/discharging_started.sh"
+# full_handler = "~/.config/batteryctl/scripts/full.sh"
+
+# The defaults of the desktop notifications. The summary and body can use the handler
+# environment variables in lowercase, without the BATTERYCTL_ prefix, like {device} or {capacity}.
+# [notifications]
+# app_name = "batteryctl"
+# summary = "Battery {event}"
+# body = "{device} is at {capacity}%"
+# timeout_ms = -1
+
+# Where the daemon records every reading, in $XDG_STATE_HOME/batteryctl/history.tsv.
+# [history]
+# enabled = true
+# max_size_kb = 1024
+# max_files = 5
+# max_age_days = 180
+
+# The daemon records the full capacity daily and fires this once the battery wears below
+# the replacement threshold, in percent of its design capacity.
+# [health]
+# replacement_percent = 70
+# handler = "~/.config/batteryctl/scripts/replacement_due.sh"
+# notify = true
+
+# The charge limits the daemon keeps applied, for drivers with charge_control_*_threshold
+# or charge_behaviour. Writing them needs root.
+# [[charge_limit]]
+# battery = "BAT0"
+# start = 75
+# end = 80
+# behaviour = "auto"
 
+# The capacity thresholds. Each one has:
+#   name:       Shown in the daemon log.
+#   percentage: The capacity to react to.
+#   minutes:    Or the estimated minutes left until empty, or full for rising thresholds.
+#   direction:  "falling" fires while discharging, "rising" while charging. [Default: "falling"]
+#   battery:    Only watch this battery. [Default: all of them]
+#   handler:    The command to run, see above.
+#   notify:     Show a desktop notification, either `true` or a table overriding any of
+#               `summary`, `body`, `urgency` ("low", "normal", "critical") and `icon`.
+#   action:     "suspend", "hibernate", "hybrid-sleep" or "power-off" the system through logind.
+#   repeat:     "crossing" fires once per crossing, "once" until the daemon restarts and
+#               "every-read" on every read past the percentage. [Default: "crossing"]
+
+[[threshold]]
+name = "good"
+percentage = 75
+# handler = "~/.config/batteryctl/scripts/good_capacity.sh"
+
+[[threshold]]
+name = "okay"
+percentage = 50
+# handler = "~/.config/batteryctl/scripts/okay_capacity.sh"
+
+[[threshold]]
+name = "bad"
+percentage = 20
+notify = true
+# handler = "~/.config/batteryctl/scripts/bad_capacity.sh"
+
+[[threshold]]
+name = "critical"
+percentage = 5
+notify = { urgency = "critical" }
+# action = "hibernate"
+# handler = "~/.config/batteryctl/scripts/critical_capacity.sh"
+
+# [[threshold]]
+# name = "last-call"
+# minutes = 15
+# notify = true
+
+# [[threshold]]
+# name = "unplug"
+# percentage = 80
+# direction = "rising"
+# handler = "~/.config/batteryctl/scripts/unplug.sh"
+"##;
@@ -0,0 +1,207 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::fmt;
+
+use crate::battery::Status;
+use crate::history::Sample;
+
+/// Samples further apart than this belong to different sessions, e.g. when
+/// the daemon wasn't running in between.
+const MAX_GAP_SECS: u64 = 30 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Charge,
+    Discharge,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::Charge => write!(f, "charge"),
+            Kind::Discharge => write!(f, "discharge"),
+        }
+    }
+}
+
+impl Kind {
+    fn of(status: Option<Status>) -> Option<Self> {
+        match status? {
+            Status::Charging => Some(Kind::Charge),
+            Status::Discharging => Some(Kind::Discharge),
+            _ => None,
+        }
+    }
+}
+
+/// An uninterrupted stretch of charging or discharging of a battery.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub device: String,
+    pub kind: Kind,
+    pub start: u64,
+    pub end: u64,
+    pub start_capacity: Option<u8>,
+    pub end_capacity: Option<u8>,
+    /// The energy that flowed in or out of the battery.
+    pub energy_wh: Option<f64>,
+    pub average_power_w: Option<f64>,
+    pub peak_power_w: Option<f64>,
+}
+
+/// Splits samples into sessions, ordered by their start. A session ends when
+/// the status changes or the samples have a gap; sessions with a single
+/// sample are dropped, since they say nothing about the power draw.
+pub fn detect(samples: &[Sample]) -> Vec<Session> {
+    let mut devices: Vec<&str> = samples.iter().map(|s| s.device.as_str()).collect();
+    devices.sort();
+    devices.dedup();
+
+    let mut sessions = Vec::new();
+    for device in devices {
+        let mut current: Vec<&Sample> = Vec::new();
+        for sample in samples.iter().filter(|s| s.device == device) {
+            let continues = current.last().is_some_and(|last| {
+                Kind::of(last.status) == Kind::of(sample.status)
+                    && sample.timestamp.saturating_sub(last.timestamp) <= MAX_GAP_SECS
+            });
+            if !continues {
+                sessions.extend(summarize(&current));
+                current.clear();
+            }
+            if Kind::of(sample.status).is_some() {
+                current.push(sample);
+            }
+        }
+        sessions.extend(summarize(&current));
+    }
+
+    sessions.sort_by_key(|s| s.start);
+    sessions
+}
+
+fn summarize(samples: &[&Sample]) -> Option<Session> {
+    let (first, last) = match samples {
+        [first, .., last] => (first, last),
+        _ => return None,
+    };
+
+    let energy_wh = match (first.energy_now, last.energy_now) {
+        (Some(start), Some(end)) => Some(start.abs_diff(end) as f64 / 1e6),
+        _ => None,
+    };
+    let powers: Vec<f64> = samples
+        .iter()
+        .filter_map(|s| s.power_now)
+        .map(|p| p as f64 / 1e6)
+        .collect();
+    let hours = (last.timestamp - first.timestamp) as f64 / 3600.0;
+
+    let average_power_w = match (powers.len(), energy_wh) {
+        (0, Some(energy)) if hours > 0.0 => Some(energy / hours),
+        (0, _) => None,
+        (n, _) => Some(powers.iter().sum::<f64>() / n as f64),
+    };
+
+    Some(Session {
+        device: first.device.clone(),
+        kind: Kind::of(first.status)?,
+        start: first.timestamp,
+        end: last.timestamp,
+        start_capacity: first.capacity,
+        end_capacity: last.capacity,
+        energy_wh,
+        average_power_w,
+        peak_power_w: powers.into_iter().reduce(f64::max),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp: u64, status: Status, capacity: u8, energy_now: u64, power_now: Option<u64>) -> Sample {
+        Sample {
+            timestamp,
+            device: "BAT0".to_string(),
+            capacity: Some(capacity),
+            status: Some(status),
+            energy_now: Some(energy_now),
+            power_now,
+            voltage_now: None,
+            temp: None,
+        }
+    }
+
+    #[test]
+    fn splits_on_status_changes_and_gaps() {
+        let samples = [
+            sample(0, Status::Discharging, 90, 45_000_000, Some(8_000_000)),
+            sample(600, Status::Discharging, 85, 42_000_000, Some(12_000_000)),
+            sample(1200, Status::Discharging, 80, 40_000_000, Some(10_000_000)),
+            sample(1800, Status::Charging, 80, 40_000_000, Some(30_000_000)),
+            sample(3600, Status::Charging, 95, 47_500_000, Some(20_000_000)),
+            sample(4200, Status::Full, 100, 50_000_000, None),
+            // The machine was off for an hour.
+            sample(9000, Status::Discharging, 100, 50_000_000, None),
+            sample(10800, Status::Discharging, 90, 45_000_000, None),
+            sample(20000, Status::Discharging, 60, 30_000_000, None),
+        ];
+
+        let sessions = detect(&samples);
+
+        assert_eq!(sessions, vec![
+            Session {
+                device: "BAT0".to_string(),
+                kind: Kind::Discharge,
+                start: 0,
+                end: 1200,
+                start_capacity: Some(90),
+                end_capacity: Some(80),
+                energy_wh: Some(5.0),
+                average_power_w: Some(10.0),
+                peak_power_w: Some(12.0),
+            },
+            Session {
+                device: "BAT0".to_string(),
+                kind: Kind::Charge,
+                start: 1800,
+                end: 3600,
+                start_capacity: Some(80),
+                end_capacity: Some(95),
+                energy_wh: Some(7.5),
+                average_power_w: Some(25.0),
+                peak_power_w: Some(30.0),
+            },
+            // Without a power draw, the average comes from the energy.
+            Session {
+                device: "BAT0".to_string(),
+                kind: Kind::Discharge,
+                start: 9000,
+                end: 10800,
+                start_capacity: Some(100),
+                end_capacity: Some(90),
+                energy_wh: Some(5.0),
+                average_power_w: Some(10.0),
+                peak_power_w: None,
+            },
+        ]);
+    }
+}
@@ -0,0 +1,149 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::time::Duration;
+
+use crate::battery::{Battery, Status};
+
+/// All the system batteries combined, as if they were one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Total {
+    /// The energy left in percent of the energy when full, so a big battery
+    /// counts for more than a small one. `None` unless every battery reports its energy.
+    pub capacity_percent: Option<f64>,
+    pub status: Option<Status>,
+    pub energy_wh: Option<f64>,
+    pub energy_full_wh: Option<f64>,
+    /// The power flowing in or out of all the batteries.
+    pub power_w: Option<f64>,
+    pub time_to_empty: Option<Duration>,
+    pub time_to_full: Option<Duration>,
+}
+
+impl Total {
+    pub fn of(batteries: &[Battery]) -> Self {
+        let sum = |values: Vec<Option<f64>>| values.into_iter().sum::<Option<f64>>();
+        let energy_wh = sum(batteries.iter().map(Battery::energy_wh).collect());
+        let energy_full_wh = sum(batteries.iter().map(Battery::energy_full_wh).collect());
+        let status = status(batteries);
+
+        // Only the batteries moving in the combined direction count, e.g. an
+        // idle second battery doesn't add to the draw but does to the energy left.
+        let power_while = |status: Status| batteries
+            .iter()
+            .filter(|b| b.status == Some(status))
+            .filter_map(Battery::power_w)
+            .sum::<f64>();
+        let hours = |energy: Option<f64>, power: f64| match energy {
+            Some(energy) if power > 0.0 => Some(Duration::from_secs_f64(energy / power * 3600.0)),
+            _ => None,
+        };
+        let (time_to_empty, time_to_full) = match status {
+            Some(Status::Discharging) => (hours(energy_wh, power_while(Status::Discharging)), None),
+            Some(Status::Charging) => {
+                let missing = energy_full_wh.zip(energy_wh).map(|(full, now)| (full - now).max(0.0));
+                (None, hours(missing, power_while(Status::Charging)))
+            }
+            _ => (None, None),
+        };
+
+        Self {
+            capacity_percent: match (energy_wh, energy_full_wh) {
+                (Some(now), Some(full)) if full > 0.0 => Some(now / full * 100.0),
+                _ => None,
+            },
+            status,
+            energy_wh,
+            energy_full_wh,
+            power_w: batteries.iter().map(Battery::power_w).sum(),
+            time_to_empty,
+            time_to_full,
+        }
+    }
+}
+
+/// The combined status: charging if any battery charges, discharging if any
+/// discharges, and full only once all of them are.
+fn status(batteries: &[Battery]) -> Option<Status> {
+    let statuses: Vec<Option<Status>> = batteries.iter().map(|b| b.status).collect();
+
+    if statuses.contains(&Some(Status::Charging)) {
+        Some(Status::Charging)
+    } else if statuses.contains(&Some(Status::Discharging)) {
+        Some(Status::Discharging)
+    } else if !statuses.is_empty() && statuses.iter().all(|s| *s == Some(Status::Full)) {
+        Some(Status::Full)
+    } else if statuses.contains(&Some(Status::NotCharging)) {
+        Some(Status::NotCharging)
+    } else {
+        statuses.into_iter().flatten().next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn battery(status: Status, energy_now: u64, energy_full: u64, power_now: i64) -> Battery {
+        Battery {
+            status: Some(status),
+            energy_now: Some(energy_now),
+            energy_full: Some(energy_full),
+            power_now: Some(power_now),
+            ..Battery::default()
+        }
+    }
+
+    #[test]
+    fn weighs_the_capacity_by_energy() {
+        // 90% of 20 Wh and 10% of 80 Wh is 26 Wh of 100, not the average of 50%.
+        let total = Total::of(&[
+            battery(Status::NotCharging, 18_000_000, 20_000_000, 0),
+            battery(Status::Discharging, 8_000_000, 80_000_000, 13_000_000),
+        ]);
+
+        assert!((total.capacity_percent.unwrap() - 26.0).abs() < 1e-9);
+        assert_eq!(total.status, Some(Status::Discharging));
+        assert_eq!(total.time_to_empty, Some(Duration::from_secs(2 * 3600)));
+        assert_eq!(total.time_to_full, None);
+    }
+
+    #[test]
+    fn charges_towards_the_combined_full_energy() {
+        let total = Total::of(&[
+            battery(Status::Full, 20_000_000, 20_000_000, 0),
+            battery(Status::Charging, 40_000_000, 80_000_000, 20_000_000),
+        ]);
+
+        assert_eq!(total.status, Some(Status::Charging));
+        assert_eq!(total.time_to_full, Some(Duration::from_secs(2 * 3600)));
+    }
+
+    #[test]
+    fn is_unknown_without_every_batterys_energy() {
+        let total = Total::of(&[
+            battery(Status::Full, 20_000_000, 20_000_000, 0),
+            Battery { status: Some(Status::Full), capacity: Some(100), ..Battery::default() },
+        ]);
+
+        assert_eq!(total.capacity_percent, None);
+        assert_eq!(total.status, Some(Status::Full));
+        assert_eq!(Total::of(&[]).status, None);
+    }
+}
@@ -0,0 +1,162 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::{io, path::Path, time::Duration};
+
+use crate::battery::{BatteryError, Properties};
+use crate::history;
+use crate::netlink;
+
+/// What `watch` follows when no `--properties` are given.
+pub const DEFAULT_PROPERTIES: [&str; 3] = ["capacity", "status", "power_now"];
+
+/// How often `watch` re-reads the device when no `--interval` is given, in
+/// milliseconds. Uevents cut the wait short, but not every driver sends them.
+pub const DEFAULT_INTERVAL_MS: u64 = 5000;
+
+/// A property that took a new value. `previous` is `None` for the values read
+/// when watching starts and for properties the device didn't have before.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub timestamp: u64,
+    pub device: String,
+    pub property: String,
+    pub value: Option<String>,
+    pub previous: Option<String>,
+}
+
+/// Reads the watched properties in order. A device that went away, like an
+/// unplugged peripheral, has none of them.
+pub fn read(root: &Path, device: &str, properties: &[String]) -> Result<Vec<Option<String>>, BatteryError> {
+    if !root.join(device).is_dir() {
+        return Ok(vec![None; properties.len()]);
+    }
+
+    let props = Properties::load(root, device)?;
+    properties.iter().map(|name| props.get(name)).collect()
+}
+
+/// Returns the properties whose value differs between two reads.
+pub fn changes(device: &str, properties: &[String], previous: &[Option<String>], current: &[Option<String>], timestamp: u64) -> Vec<Change> {
+    properties
+        .iter()
+        .zip(previous.iter().zip(current))
+        .filter(|(_, (old, new))| old != new)
+        .map(|(property, (old, new))| Change {
+            timestamp,
+            device: device.to_string(),
+            property: property.clone(),
+            value: new.clone(),
+            previous: old.clone(),
+        })
+        .collect()
+}
+
+/// Calls `emit` with the current value of every property the device has, then
+/// with the changes of every read that found some, until `emit` fails, e.g.
+/// because the reader of a pipe went away.
+pub fn run(
+    root: &Path,
+    device: &str,
+    properties: &[String],
+    interval: Duration,
+    mut emit: impl FnMut(&[Change]) -> io::Result<()>,
+) -> Result<(), String> {
+    let mut values = Properties::load(root, device)
+        .and_then(|_| read(root, device, properties))
+        .map_err(|e| e.to_string())?;
+    if emit(&changes(device, properties, &vec![None; properties.len()], &values, history::now())).is_err() {
+        return Ok(());
+    }
+
+    let listener = match netlink::Listener::open() {
+        Ok(val) => Some(val),
+        Err(e) => {
+            eprintln!("[WARN] Can't listen for uevents, falling back to polling: {e}");
+            None
+        }
+    };
+
+    let mut last_error = None;
+    loop {
+        netlink::wait_for_power_supply(listener.as_ref(), interval, |event| event.device_name() == Some(device));
+
+        let current = match read(root, device, properties) {
+            Ok(val) => val,
+            Err(e) => {
+                let e = e.to_string();
+                if last_error.as_ref() != Some(&e) {
+                    eprintln!("[ERROR] Reading {device} failed: {e}");
+                    last_error = Some(e);
+                }
+                continue;
+            }
+        };
+        last_error = None;
+
+        let changes = changes(device, properties, &values, &current, history::now());
+        if !changes.is_empty() && emit(&changes).is_err() {
+            return Ok(());
+        }
+        values = current;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(values: &[Option<&str>]) -> Vec<Option<String>> {
+        values.iter().map(|v| v.map(str::to_string)).collect()
+    }
+
+    #[test]
+    fn reports_only_the_properties_that_changed() {
+        let properties: Vec<String> = DEFAULT_PROPERTIES.iter().map(|p| p.to_string()).collect();
+        let previous = values(&[Some("87"), Some("Discharging"), Some("9120000")]);
+        let current = values(&[Some("86"), Some("Discharging"), None]);
+
+        let changes = changes("BAT0", &properties, &previous, &current, 100);
+
+        assert_eq!(changes, vec![
+            Change {
+                timestamp: 100,
+                device: "BAT0".to_string(),
+                property: "capacity".to_string(),
+                value: Some("86".to_string()),
+                previous: Some("87".to_string()),
+            },
+            Change {
+                timestamp: 100,
+                device: "BAT0".to_string(),
+                property: "power_now".to_string(),
+                value: None,
+                previous: Some("9120000".to_string()),
+            },
+        ]);
+    }
+
+    #[test]
+    fn a_removed_device_has_no_properties() {
+        let dir = std::env::temp_dir().join(format!("batteryctl-watch-{}", std::process::id()));
+        let properties = vec!["capacity".to_string()];
+
+        assert_eq!(read(&dir, "BAT9", &properties).unwrap(), vec![None]);
+    }
+}
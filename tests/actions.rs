@@ -0,0 +1,151 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+mod common;
+
+use std::{
+    fs,
+    process::Stdio,
+    sync::{Arc, Mutex},
+    thread::sleep,
+    time::Duration,
+};
+
+use common::{stdout, wait_until, KillOnDrop, PrivateBus, SysfsFixture};
+
+/// A logind that records the power actions it's asked for instead of performing them.
+struct StubLogind {
+    calls: Arc<Mutex<Vec<String>>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Manager")]
+impl StubLogind {
+    fn suspend(&self, interactive: bool) {
+        self.calls.lock().unwrap().push(format!("Suspend({interactive})"));
+    }
+
+    fn hibernate(&self, interactive: bool) {
+        self.calls.lock().unwrap().push(format!("Hibernate({interactive})"));
+    }
+
+    fn hybrid_sleep(&self, interactive: bool) {
+        self.calls.lock().unwrap().push(format!("HybridSleep({interactive})"));
+    }
+
+    fn power_off(&self, interactive: bool) {
+        self.calls.lock().unwrap().push(format!("PowerOff({interactive})"));
+    }
+}
+
+fn serve_logind(bus: &PrivateBus, calls: &Arc<Mutex<Vec<String>>>) -> zbus::blocking::Connection {
+    zbus::blocking::connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name("org.freedesktop.login1")
+        .unwrap()
+        .serve_at("/org/freedesktop/login1", StubLogind { calls: calls.clone() })
+        .unwrap()
+        .build()
+        .unwrap()
+}
+
+fn action_config(action: &str, countdown_secs: u64) -> String {
+    format!(concat!(
+        "batteries = [\"BAT0\"]\n",
+        "read_delay_ms = 20\n",
+        "action_countdown_secs = {}\n",
+        "\n",
+        "[[threshold]]\n",
+        "name = \"critical\"\n",
+        "percentage = 5\n",
+        "action = \"{}\"\n",
+    ), countdown_secs, action)
+}
+
+#[test]
+fn daemon_performs_actions_through_logind() {
+    let bus = match PrivateBus::start() {
+        Some(val) => val,
+        None => return eprintln!("dbus-daemon isn't installed, skipping"),
+    };
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let _logind = serve_logind(&bus, &calls);
+
+    let fixture = SysfsFixture::laptop();
+    fixture.write_config(&action_config("hibernate", 0));
+
+    let _daemon = KillOnDrop(fixture.command(&["daemon"])
+        .env("DBUS_SYSTEM_BUS_ADDRESS", &bus.address)
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap());
+
+    fixture.set("BAT0", "capacity", "5");
+    assert!(wait_until(|| !calls.lock().unwrap().is_empty()));
+    assert_eq!(*calls.lock().unwrap(), vec!["Hibernate(false)".to_string()]);
+}
+
+#[test]
+fn connecting_power_cancels_the_countdown() {
+    let bus = match PrivateBus::start() {
+        Some(val) => val,
+        None => return eprintln!("dbus-daemon isn't installed, skipping"),
+    };
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let _logind = serve_logind(&bus, &calls);
+
+    let fixture = SysfsFixture::laptop();
+    fixture.write_config(&action_config("suspend", 1));
+
+    let daemon = KillOnDrop(fixture.command(&["daemon"])
+        .env("DBUS_SYSTEM_BUS_ADDRESS", &bus.address)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap());
+
+    fixture.set("BAT0", "capacity", "5");
+    sleep(Duration::from_millis(300));
+    fixture.set("AC", "online", "1");
+    sleep(Duration::from_millis(1500));
+
+    let output = daemon.finish();
+    assert!(calls.lock().unwrap().is_empty());
+    assert!(stdout(&output).contains("[WARN] Performing the suspend in 1s unless power is connected\n"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("[INFO] Power connected, cancelled the suspend\n"), "{}", stdout(&output));
+}
+
+#[test]
+fn falls_back_to_sys_power_without_logind() {
+    let fixture = SysfsFixture::laptop();
+    fixture.write_config(&action_config("hybrid-sleep", 0));
+    let sys_power = fixture.scratch("power");
+    fs::create_dir_all(&sys_power).unwrap();
+
+    let _daemon = KillOnDrop(fixture.command(&["daemon"])
+        .env("DBUS_SYSTEM_BUS_ADDRESS", format!("unix:path={}", fixture.scratch("no-bus").display()))
+        .env("BATTERYCTL_SYS_POWER", &sys_power)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap());
+
+    fixture.set("BAT0", "capacity", "5");
+    assert!(wait_until(|| fs::read_to_string(sys_power.join("state")).is_ok()));
+    assert_eq!(fs::read_to_string(sys_power.join("disk")).unwrap(), "suspend");
+    assert_eq!(fs::read_to_string(sys_power.join("state")).unwrap(), "disk");
+}
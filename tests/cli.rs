@@ -0,0 +1,948 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+mod common;
+
+use std::{fs, process::Stdio};
+
+use common::{stderr, stdout, wait_until, KillOnDrop, SysfsFixture};
+
+#[test]
+fn list_devices_lists_every_power_supply() {
+    let fixture = SysfsFixture::laptop();
+
+    let output = fixture.run(&["list-devices"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "AC\nBAT0\nBAT1\nucsi-source-psy-USBC000:001\n");
+}
+
+#[test]
+fn get_property_reads_typed_properties() {
+    let fixture = SysfsFixture::laptop();
+
+    let output = fixture.run(&["get-property", "capacity"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "87\n");
+
+    let output = fixture.run(&["get-property", "--device", "BAT1", "status"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "Not charging\n");
+}
+
+#[test]
+fn get_property_falls_back_to_raw_attributes() {
+    let fixture = SysfsFixture::laptop();
+
+    let output = fixture.run(&["get-property", "--device", "BAT0", "voltage_min_design"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "11520000\n");
+}
+
+#[test]
+fn get_property_reports_missing_attributes() {
+    let fixture = SysfsFixture::laptop();
+
+    let output = fixture.run(&["get-property", "--device", "AC", "capacity"]);
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("capacity"), "{}", stderr(&output));
+}
+
+#[test]
+fn get_property_reports_parse_errors_instead_of_panicking() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "capacity", "eighty");
+
+    let output = fixture.run(&["get-property", "capacity"]);
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Invalid value 'eighty'"), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("panicked"), "{}", stderr(&output));
+}
+
+#[test]
+fn get_property_rejects_unknown_devices() {
+    let fixture = SysfsFixture::laptop();
+
+    let output = fixture.run(&["get-property", "--device", "BAT9", "capacity"]);
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("BAT9"), "{}", stderr(&output));
+}
+
+#[test]
+fn sysfs_root_can_be_set_through_the_environment() {
+    let fixture = SysfsFixture::laptop();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_batteryctl"))
+        .args(["get-property", "capacity"])
+        .env("BATTERYCTL_SYSFS_ROOT", fixture.root())
+        .env("XDG_CONFIG_HOME", fixture.config_home())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "87\n");
+}
+
+#[test]
+fn daemon_fails_on_missing_batteries() {
+    let fixture = SysfsFixture::laptop();
+    fixture.write_config(concat!(
+        "batteries = [\"BAT9\"]\n",
+        "read_delay_ms = 10\n",
+        "good_capacity = 75\n",
+        "okay_capacity = 50\n",
+        "bad_capacity = 20\n",
+        "critical_capacity = 5\n",
+    ));
+
+    let output = fixture.run(&["daemon"]);
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("BAT9"), "{}", stderr(&output));
+}
+
+#[test]
+fn config_keys_are_validated() {
+    let fixture = SysfsFixture::laptop();
+
+    let output = fixture.run(&["config-get", "read-delay"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "180000\n");
+
+    let output = fixture.run(&["config-get", "capacity"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Invalid config key 'capacity'"), "{}", stderr(&output));
+}
+
+#[test]
+fn invalid_configs_are_reported_and_kept() {
+    let fixture = SysfsFixture::laptop();
+    let contents = concat!(
+        "batteries = [\"BAT0\"]\n",
+        "read_delay_ms = 1000\n",
+        "[[threshold]]\n",
+        "name = \"critical\"\n",
+        "percentage = 5\n",
+        "action = \"shutdown\"\n",
+    );
+    fixture.write_config(contents);
+    let path = fixture.config_home().join("batteryctl").join("config.toml");
+
+    let output = fixture.run(&["config-get", "read-delay"]);
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains(&format!("Invalid config {}", path.display())), "{}", stderr(&output));
+    assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+}
+
+#[test]
+fn get_property_all_dumps_uevent_and_attribute_files() {
+    let fixture = SysfsFixture::laptop();
+
+    let output = fixture.run(&["get-property", "--all", "--device", "ucsi-source-psy-USBC000:001"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), concat!(
+        "current_max=0\n",
+        "name=ucsi-source-psy-USBC000:001\n",
+        "online=0\n",
+        "scope=System\n",
+        "type=USB\n",
+        "usb_type=C [PD] PD_PPS\n",
+        "voltage_max=5000000\n",
+    ));
+}
+
+#[test]
+fn get_property_reads_devices_that_only_expose_uevent() {
+    let fixture = SysfsFixture::new();
+    fixture.device("hidpp_battery_0");
+    fs::write(fixture.device_path("hidpp_battery_0").join("uevent"), concat!(
+        "POWER_SUPPLY_NAME=hidpp_battery_0\n",
+        "POWER_SUPPLY_TYPE=Battery\n",
+        "POWER_SUPPLY_SCOPE=Device\n",
+        "POWER_SUPPLY_STATUS=Discharging\n",
+        "POWER_SUPPLY_CAPACITY=42\n",
+    )).unwrap();
+
+    let output = fixture.run(&["get-property", "--device", "hidpp_battery_0", "capacity"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "42\n");
+}
+
+#[test]
+fn get_property_requires_a_name_or_all() {
+    let fixture = SysfsFixture::laptop();
+
+    let output = fixture.run(&["get-property"]);
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("--all"), "{}", stderr(&output));
+}
+
+#[test]
+fn time_left_estimates_from_the_power_draw() {
+    let fixture = SysfsFixture::laptop();
+
+    assert_eq!(stdout(&fixture.run(&["time-left"])), "4h 34m until empty\n");
+    assert_eq!(stdout(&fixture.run(&["time-left", "-d", "BAT1"])), "Unknown\n");
+
+    fixture.set("BAT0", "status", "Charging");
+    assert_eq!(stdout(&fixture.run(&["time-left"])), "0h 41m until full\n");
+}
+
+#[test]
+fn health_reports_wear_and_a_verdict() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT1", "manufacture_year", "2021");
+    fixture.set("BAT1", "manufacture_month", "3");
+
+    let output = fixture.run(&["health", "--all"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), concat!(
+        "BAT0\n",
+        "  verdict:          good\n",
+        "  health:           84.2% (48.00 of 57.02 Wh)\n",
+        "  wear:             15.8%\n",
+        "  cycle_count:      212\n",
+        "  technology:       Li-poly\n",
+        "  manufacture_date: -\n",
+        "  voltage:          12.11 V, 5.1% above the 11.52 V design minimum\n",
+        "\n",
+        "BAT1\n",
+        "  verdict:          good\n",
+        "  health:           88.2% (3.00 of 3.40 Ah)\n",
+        "  wear:             11.8%\n",
+        "  cycle_count:      0\n",
+        "  technology:       Li-ion\n",
+        "  manufacture_date: 2021-03\n",
+        "  voltage:          11.87 V, 4.1% above the 11.40 V design minimum\n",
+    ));
+}
+
+#[test]
+fn health_downgrades_worn_and_sagging_batteries() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "energy_full", "40000000");
+    fixture.set("BAT0", "voltage_now", "11000000");
+
+    let output = fixture.run(&["health"]);
+
+    assert!(stdout(&output).contains("  verdict:          poor\n"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("  voltage:          11.00 V, 4.5% below the 11.52 V design minimum\n"), "{}", stdout(&output));
+}
+
+#[test]
+fn set_property_lists_writable_properties() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "charge_control_end_threshold", "100");
+    fixture.set("BAT0", "charge_behaviour", "[auto] inhibit-charge");
+    fixture.set("BAT0", "alarm", "0");
+    fixture.writable_only("BAT0", &["charge_control_end_threshold", "charge_behaviour", "alarm"]);
+
+    let output = fixture.run(&["set-property"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), concat!(
+        "PROPERTY                      VALUE                  ACCEPTS\n",
+        "alarm                         0                      -\n",
+        "charge_behaviour              [auto] inhibit-charge  auto, inhibit-charge\n",
+        "charge_control_end_threshold  100                    0-100\n",
+    ));
+}
+
+#[test]
+fn set_property_validates_before_writing() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "charge_control_end_threshold", "100");
+    fixture.set("BAT0", "alarm", "0");
+    fixture.writable_only("BAT0", &["charge_control_end_threshold", "alarm"]);
+    let read = |name: &str| fs::read_to_string(fixture.device_path("BAT0").join(name)).unwrap();
+
+    let output = fixture.run(&["set-property", "charge_control_end_threshold", "80", "--dry-run"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), format!(
+        "Would write '80' to {} (currently '100')\n",
+        fixture.device_path("BAT0").join("charge_control_end_threshold").display(),
+    ));
+    assert_eq!(read("charge_control_end_threshold"), "100\n");
+
+    let output = fixture.run(&["set-property", "charge_control_end_threshold", "180"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("expected 0-100"), "{}", stderr(&output));
+
+    let output = fixture.run(&["set-property", "charge_control_end_threshold", "80"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(read("charge_control_end_threshold"), "80");
+
+    let output = fixture.run(&["set-property", "capacity", "50"]);
+    assert!(stderr(&output).contains("'capacity' of BAT0 is read-only"), "{}", stderr(&output));
+
+    // Attributes batteryctl doesn't know are written unchecked.
+    let output = fixture.run(&["set-property", "alarm", "1200000"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("[WARN]"), "{}", stderr(&output));
+    assert_eq!(read("alarm"), "1200000");
+}
+
+#[test]
+fn charge_limit_reads_and_sets_the_thresholds() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "charge_control_start_threshold", "0");
+    fixture.set("BAT0", "charge_control_end_threshold", "100");
+    fixture.set("BAT0", "charge_behaviour", "[auto] inhibit-charge force-discharge");
+
+    let output = fixture.run(&["charge-limit"]);
+    assert_eq!(stdout(&output), concat!(
+        "BAT0\n",
+        "  start_threshold:  0%\n",
+        "  end_threshold:    100%\n",
+        "  behaviour:        auto (available: auto, inhibit-charge, force-discharge)\n",
+    ));
+
+    let output = fixture.run(&["charge-limit", "--start", "75", "--end", "80", "--behaviour", "inhibit-charge"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("  end_threshold:    80%\n"), "{}", stdout(&output));
+    let read = |name: &str| fs::read_to_string(fixture.device_path("BAT0").join(name)).unwrap();
+    assert_eq!(read("charge_control_start_threshold"), "75");
+    assert_eq!(read("charge_behaviour"), "inhibit-charge");
+}
+
+#[test]
+fn charge_limit_rejects_what_the_driver_does_not_support() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "charge_control_end_threshold", "80");
+
+    let output = fixture.run(&["charge-limit", "--start", "40"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("BAT0 doesn't support a charge start threshold"), "{}", stderr(&output));
+
+    let output = fixture.run(&["charge-limit", "--end", "120"]);
+    assert!(!output.status.success());
+
+    let output = fixture.run(&["charge-limit", "-d", "BAT1"]);
+    assert!(stderr(&output).contains("BAT1 doesn't support charge control"), "{}", stderr(&output));
+}
+
+#[test]
+fn list_devices_filters_by_type_and_scope() {
+    let fixture = SysfsFixture::laptop();
+    fixture.device("hidpp_battery_0")
+        .attr("type", "Battery")
+        .attr("scope", "Device")
+        .attr("capacity", "40");
+
+    let output = fixture.run(&["list-devices", "--type", "battery"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "BAT0\nBAT1\nhidpp_battery_0\n");
+
+    let output = fixture.run(&["list-devices", "--type", "battery", "--scope", "system"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "BAT0\nBAT1\n");
+
+    // Devices without a scope attribute count as system devices.
+    let output = fixture.run(&["list-devices", "--type", "mains", "--scope", "system"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "AC\n");
+}
+
+#[test]
+fn list_devices_survives_devices_it_cannot_parse() {
+    let fixture = SysfsFixture::laptop();
+    fixture.device("wacom_battery_0")
+        .attr("type", "Stylus")
+        .attr("scope", "Pen");
+    fixture.device("broken")
+        .attr("type", "Mains")
+        .attr("online", "yes");
+
+    let output = fixture.run(&["list-devices", "--type", "unknown"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "wacom_battery_0\n");
+    assert!(stderr(&output).contains("[WARN] Skipping broken: "), "{}", stderr(&output));
+
+    let output = fixture.run(&["status"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn commands_skip_peripherals_with_unparsable_values() {
+    let fixture = SysfsFixture::laptop();
+    fixture.device("hid")
+        .attr("type", "Battery")
+        .attr("scope", "Device")
+        .attr("capacity", "-1");
+
+    for args in [&["list-devices", "--wide"][..], &["list-devices", "-o", "json"]] {
+        let output = fixture.run(args);
+
+        assert!(output.status.success(), "{:?}: {}", args, stderr(&output));
+        assert!(stderr(&output).contains("[WARN] Skipping hid: "), "{:?}: {}", args, stderr(&output));
+        assert!(stdout(&output).contains("BAT0"), "{:?}: {}", args, stdout(&output));
+    }
+
+    // Peripherals aren't part of the system's battery health at all.
+    let output = fixture.run(&["health", "--all"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("BAT1"), "{}", stdout(&output));
+    assert!(!stdout(&output).contains("hid"), "{}", stdout(&output));
+}
+
+#[test]
+fn list_devices_wide_prints_a_table() {
+    let fixture = SysfsFixture::laptop();
+
+    let output = fixture.run(&["list-devices", "--wide"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), concat!(
+        "NAME                         TYPE     SCOPE   STATE    MODEL       CAPACITY  STATUS\n",
+        "AC                           Mains    System  offline  -           -         -\n",
+        "BAT0                         Battery  System  present  5B10W13930  87%       Discharging\n",
+        "BAT1                         Battery  System  present  01AV405     64%       Not charging\n",
+        "ucsi-source-psy-USBC000:001  USB      System  offline  -           -         -\n",
+    ));
+}
+
+#[test]
+fn daemon_fires_each_handler_once_per_crossing() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "capacity", "60");
+    let log = fixture.scratch("handlers.log");
+    fixture.write_config(&format!(concat!(
+        "batteries = [\"BAT0\"]\n",
+        "read_delay_ms = 20\n",
+        "good_capacity = 75\n",
+        "okay_capacity = 50\n",
+        "bad_capacity = 20\n",
+        "critical_capacity = 5\n",
+        "good_capacity_handler = \"echo good >> {log}\"\n",
+        "okay_capacity_handler = \"echo okay >> {log}\"\n",
+    ), log = log.display()));
+    let read_log = || fs::read_to_string(&log).unwrap_or_default();
+
+    let _daemon = KillOnDrop(fixture.command(&["daemon"])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap());
+
+    assert!(wait_until(|| read_log() == "good BAT0\n"), "{:?}", read_log());
+
+    fixture.set("BAT0", "capacity", "50");
+    assert!(wait_until(|| read_log() == "good BAT0\nokay BAT0\n"), "{:?}", read_log());
+
+    // Hovering around the threshold must not fire the handler again.
+    fixture.set("BAT0", "capacity", "51");
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    fixture.set("BAT0", "capacity", "50");
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert_eq!(read_log(), "good BAT0\nokay BAT0\n");
+}
+
+#[test]
+fn daemon_survives_batteries_it_cannot_read() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "capacity", "60");
+    let log = fixture.scratch("handlers.log");
+    fixture.write_config(&format!(concat!(
+        "batteries = [\"BAT0\"]\n",
+        "read_delay_ms = 20\n",
+        "good_capacity = 75\n",
+        "okay_capacity = 50\n",
+        "bad_capacity = 20\n",
+        "critical_capacity = 5\n",
+        "okay_capacity_handler = \"echo okay >> {log}\"\n",
+    ), log = log.display()));
+    let read_log = || fs::read_to_string(&log).unwrap_or_default();
+
+    let mut daemon = KillOnDrop(fixture.command(&["daemon"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap());
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    fixture.set("BAT0", "capacity", "unknown");
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(daemon.0.try_wait().unwrap().is_none());
+
+    fixture.set("BAT0", "capacity", "50");
+    assert!(wait_until(|| read_log() == "okay BAT0\n"), "{:?}", read_log());
+
+    let output = daemon.finish();
+    assert!(stderr(&output).contains("[WARN] Skipping BAT0 until the next read"), "{}", stderr(&output));
+}
+
+#[test]
+fn daemon_reports_ac_and_charging_changes() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "capacity", "78");
+    fixture.set("BAT0", "status", "Discharging");
+    let log = fixture.scratch("handlers.log");
+    fixture.write_config(&format!(concat!(
+        "batteries = [\"BAT0\"]\n",
+        "read_delay_ms = 20\n",
+        "good_capacity = 75\n",
+        "okay_capacity = 50\n",
+        "bad_capacity = 20\n",
+        "critical_capacity = 5\n",
+        "ac_connected_handler = \"echo connected >> {log}\"\n",
+        "charging_started_handler = \"echo charging >> {log}\"\n",
+        "\n",
+        "[[threshold]]\n",
+        "name = \"unplug\"\n",
+        "percentage = 80\n",
+        "direction = \"rising\"\n",
+        "handler = \"echo unplug >> {log}\"\n",
+    ), log = log.display()));
+    let read_log = || fs::read_to_string(&log).unwrap_or_default();
+
+    let _daemon = KillOnDrop(fixture.command(&["daemon"])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap());
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    fixture.set("AC", "online", "1");
+    fixture.set("BAT0", "status", "Charging");
+    assert!(wait_until(|| read_log() == "connected AC\ncharging BAT0\n"), "{:?}", read_log());
+
+    fixture.set("BAT0", "capacity", "80");
+    assert!(wait_until(|| read_log().ends_with("unplug BAT0\n")), "{:?}", read_log());
+}
+
+#[test]
+fn daemon_records_history() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "temp", "312");
+    fixture.write_config("batteries = [\"BAT0\", \"BAT1\"]\nread_delay_ms = 20\n");
+    let history = fixture.state_home().join("batteryctl").join("history.tsv");
+    let read_history = || fs::read_to_string(&history).unwrap_or_default();
+
+    let _daemon = KillOnDrop(fixture.command(&["daemon"])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap());
+    assert!(wait_until(|| read_history().lines().count() >= 5), "{:?}", read_history());
+
+    let contents = read_history();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert!(lines[0].starts_with("# timestamp\t"), "{:?}", lines[0]);
+    assert!(lines[1].ends_with("\tBAT0\t87\tDischarging\t41760000\t9120000\t12106000\t312"), "{:?}", lines[1]);
+    // BAT1 reports charge, which is recorded as energy.
+    assert!(lines[2].ends_with("\tBAT1\t64\tNot charging\t22790400\t0\t11870000\t"), "{:?}", lines[2]);
+}
+
+#[test]
+fn daemon_records_the_capacity_without_the_history() {
+    let fixture = SysfsFixture::laptop();
+    fixture.write_config("batteries = [\"BAT0\"]\nread_delay_ms = 20\n[history]\nenabled = false\n");
+    let state = fixture.state_home().join("batteryctl");
+
+    let _daemon = KillOnDrop(fixture.command(&["daemon"])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap());
+
+    assert!(wait_until(|| state.join("capacity.tsv").exists()));
+    assert!(!state.join("history.tsv").exists());
+}
+
+#[test]
+fn daemon_reapplies_charge_limits() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "charge_control_start_threshold", "0");
+    fixture.set("BAT0", "charge_control_end_threshold", "100");
+    fixture.write_config(concat!(
+        "batteries = [\"BAT0\"]\n",
+        "read_delay_ms = 20\n",
+        "\n",
+        "[[charge_limit]]\n",
+        "battery = \"BAT0\"\n",
+        "start = 75\n",
+        "end = 80\n",
+    ));
+    let read = |name: &str| fs::read_to_string(fixture.device_path("BAT0").join(name)).unwrap();
+
+    let _daemon = KillOnDrop(fixture.command(&["daemon"])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap());
+    assert!(wait_until(|| read("charge_control_end_threshold").trim() == "80"));
+    assert_eq!(read("charge_control_start_threshold").trim(), "75");
+
+    // Like firmware resetting the thresholds on resume.
+    fixture.set("BAT0", "charge_control_end_threshold", "100");
+    assert!(wait_until(|| read("charge_control_end_threshold").trim() == "80"));
+}
+
+#[test]
+fn daemon_reports_worn_batteries_once() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "energy_full", "39000000");
+    let log = fixture.scratch("replacement.log");
+    fixture.write_config(&format!(concat!(
+        "batteries = [\"BAT0\"]\n",
+        "read_delay_ms = 20\n",
+        "\n",
+        "[health]\n",
+        "replacement_percent = 70\n",
+        "handler = \"echo $BATTERYCTL_EVENT $BATTERYCTL_HEALTH >> {}; true\"\n",
+    ), log.display()));
+
+    // The last record, from two days ago, was still above the threshold.
+    let dir = fixture.state_home().join("batteryctl");
+    fs::create_dir_all(&dir).unwrap();
+    let two_days_ago = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() - 2 * 24 * 60 * 60;
+    fs::write(dir.join("capacity.tsv"), format!("{}\tBAT0\t41054400\t57020000\t200\n", two_days_ago)).unwrap();
+    let read_log = || fs::read_to_string(&log).unwrap_or_default();
+
+    let daemon = KillOnDrop(fixture.command(&["daemon"])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap());
+    assert!(wait_until(|| !read_log().is_empty()));
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    drop(daemon);
+
+    // A restarted daemon doesn't report it again.
+    let _daemon = KillOnDrop(fixture.command(&["daemon"])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap());
+    std::thread::sleep(std::time::Duration::from_millis(300));
+
+    assert_eq!(read_log(), "replacement-due 68.4\n");
+    let records = fs::read_to_string(dir.join("capacity.tsv")).unwrap();
+    assert_eq!(records.lines().count(), 2, "{}", records);
+    assert!(records.ends_with("\tBAT0\t39000000\t57020000\t212\n"), "{}", records);
+}
+
+#[test]
+fn daemon_passes_context_to_handlers() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "capacity", "60");
+    let log = fixture.scratch("handlers.log");
+    let handler = format!(
+        concat!(
+            "echo \\\"$BATTERYCTL_EVENT $BATTERYCTL_DEVICE $BATTERYCTL_CAPACITY $BATTERYCTL_STATUS",
+            " $BATTERYCTL_LEVEL/$BATTERYCTL_PREVIOUS_LEVEL $BATTERYCTL_THRESHOLD $BATTERYCTL_TIME_TO_EMPTY",
+            " $BATTERYCTL_POWER_W $BATTERYCTL_ENERGY_WH $BATTERYCTL_VOLTAGE_V\\\" >> {}; true",
+        ),
+        log.display(),
+    );
+    fixture.write_config(&format!(concat!(
+        "batteries = [\"BAT0\"]\n",
+        "read_delay_ms = 20\n",
+        "\n",
+        "[[threshold]]\n",
+        "name = \"good\"\n",
+        "percentage = 75\n",
+        "handler = \"{handler}\"\n",
+        "\n",
+        "[[threshold]]\n",
+        "name = \"okay\"\n",
+        "percentage = 50\n",
+        "handler = \"{handler}\"\n",
+    ), handler = handler));
+    let read_log = || fs::read_to_string(&log).unwrap_or_default();
+
+    let _daemon = KillOnDrop(fixture.command(&["daemon"])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap());
+    assert!(wait_until(|| read_log().lines().count() == 1), "{:?}", read_log());
+
+    fixture.set("BAT0", "capacity", "50");
+    assert!(wait_until(|| read_log().lines().count() == 2), "{:?}", read_log());
+
+    assert_eq!(read_log(), concat!(
+        "good BAT0 60 Discharging good/ 75 16484 9.12 41.76 12.11\n",
+        "okay BAT0 50 Discharging okay/good 50 16484 9.12 41.76 12.11\n",
+    ));
+}
+
+#[test]
+fn daemon_runs_handlers_safely() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "capacity", "60");
+    let home = fixture.scratch("home");
+    let log = fixture.scratch("handlers.log");
+    let scripts = home.join("my scripts");
+    fs::create_dir_all(&scripts).unwrap();
+    fs::write(scripts.join("record.sh"), format!("#!/bin/sh\necho \"$1|$2\" >> '{}'\n", log.display())).unwrap();
+    fs::set_permissions(scripts.join("record.sh"), std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+    fixture.write_config(&format!(concat!(
+        "batteries = [\"BAT0\"]\n",
+        "read_delay_ms = 20\n",
+        "\n",
+        "[[threshold]]\n",
+        "name = \"good\"\n",
+        "percentage = 75\n",
+        "handler = [\"~/my scripts/record.sh\", \"two words\"]\n",
+        "\n",
+        "[[threshold]]\n",
+        "name = \"okay\"\n",
+        "percentage = 50\n",
+        "handler = {{ shell = \"echo out; echo err >&2; exit 3\" }}\n",
+        "\n",
+        "[[threshold]]\n",
+        "name = \"bad\"\n",
+        "percentage = 20\n",
+        "handler = {{ shell = \"sleep 30; true\", timeout = 1 }}\n",
+        "\n",
+        "[[threshold]]\n",
+        "name = \"critical\"\n",
+        "percentage = 5\n",
+        "handler = {{ shell = \"echo done >> '{log}'\" }}\n",
+    ), log = log.display()));
+    let read_log = || fs::read_to_string(&log).unwrap_or_default();
+
+    let daemon = KillOnDrop(fixture.command(&["daemon"])
+        .env("HOME", &home)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap());
+    assert!(wait_until(|| read_log() == "two words|BAT0\n"), "{:?}", read_log());
+
+    fixture.set("BAT0", "capacity", "50");
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    fixture.set("BAT0", "capacity", "20");
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    fixture.set("BAT0", "capacity", "5");
+    assert!(wait_until(|| read_log().ends_with("done BAT0\n")), "{:?}", read_log());
+
+    let output = daemon.finish();
+    assert!(stdout(&output).contains("[INFO] okay handler: out\n"), "{}", stdout(&output));
+    assert!(stderr(&output).contains("[WARN] okay handler: err\n"), "{}", stderr(&output));
+    assert!(stderr(&output).contains("[ERROR] okay handler exited with status 3\n"), "{}", stderr(&output));
+    assert!(stderr(&output).contains("[ERROR] bad handler didn't finish within 1s and was killed\n"), "{}", stderr(&output));
+}
+
+#[test]
+fn daemon_does_not_wait_for_handlers_background_processes() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "capacity", "60");
+    let log = fixture.scratch("handlers.log");
+    fixture.write_config(&format!(concat!(
+        "batteries = [\"BAT0\"]\n",
+        "read_delay_ms = 20\n",
+        "\n",
+        "[[threshold]]\n",
+        "name = \"good\"\n",
+        "percentage = 75\n",
+        // The background sleep inherits the handler's stdout and stderr.
+        "handler = {{ shell = \"sleep 30 & echo started\" }}\n",
+        "\n",
+        "[[threshold]]\n",
+        "name = \"okay\"\n",
+        "percentage = 50\n",
+        "handler = {{ shell = \"echo okay >> '{log}'\" }}\n",
+    ), log = log.display()));
+    let read_log = || fs::read_to_string(&log).unwrap_or_default();
+
+    let daemon = KillOnDrop(fixture.command(&["daemon"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap());
+    std::thread::sleep(std::time::Duration::from_millis(500));
+
+    fixture.set("BAT0", "capacity", "50");
+    assert!(wait_until(|| read_log() == "okay BAT0\n"), "{:?}", read_log());
+
+    let output = daemon.finish();
+    assert!(stdout(&output).contains("[INFO] good handler: started BAT0\n"), "{}", stdout(&output));
+}
+
+#[test]
+fn status_summarizes_every_system_battery() {
+    let fixture = SysfsFixture::laptop();
+    // A mouse battery doesn't power the system.
+    fixture.device("hidpp_battery_0")
+        .attr("type", "Battery")
+        .attr("scope", "Device")
+        .attr("capacity", "10");
+
+    let output = fixture.run(&["status"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    // The total is weighted by energy rather than the 75.5% average.
+    assert_eq!(stdout(&output), concat!(
+        "On battery power\n",
+        "BATTERY  CAPACITY  STATUS        POWER   TIME_LEFT        ENERGY        HEALTH\n",
+        "BAT0     87%       Discharging   9.12 W  4h 34m to empty  41.8/48.0 Wh  84.2%\n",
+        "BAT1     64%       Not charging  0.00 W  -                22.8/35.6 Wh  88.2%\n",
+        "total    77.2%     Discharging   9.12 W  7h 04m to empty  64.6/83.6 Wh  -\n",
+    ));
+
+    fixture.set("AC", "online", "1");
+    fixture.set("BAT0", "status", "Charging");
+    let output = fixture.run(&["status"]);
+    assert!(stdout(&output).starts_with("On AC power (AC)\n"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("\ntotal    77.2%     Charging      9.12 W  2h 05m to full "), "{}", stdout(&output));
+}
+
+#[test]
+fn watch_prints_the_properties_that_change() {
+    let fixture = SysfsFixture::laptop();
+
+    let watch = KillOnDrop(fixture.command(&["watch", "--interval", "20"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap());
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    fixture.set("BAT0", "capacity", "86");
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    // Properties that aren't watched don't print anything.
+    fixture.set("BAT0", "voltage_now", "12000000");
+    fixture.set("BAT0", "status", "Charging");
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    let output = stdout(&watch.finish());
+    let lines: Vec<&str> = output
+        .lines()
+        .map(|line| line.split_once(' ').unwrap().1)
+        .collect();
+    assert_eq!(lines, [
+        "BAT0 capacity=87",
+        "BAT0 status=Discharging",
+        "BAT0 power_now=9120000",
+        "BAT0 capacity=86",
+        "BAT0 status=Charging",
+    ]);
+}
+
+#[test]
+fn watch_streams_json_objects() {
+    let fixture = SysfsFixture::laptop();
+
+    let watch = KillOnDrop(fixture.command(&["watch", "--device", "AC", "--properties", "online", "--interval", "20", "--output", "json"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap());
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    fixture.set("AC", "online", "1");
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    let output = stdout(&watch.finish());
+    let changes: Vec<serde_json::Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(changes.len(), 2, "{}", output);
+    assert_eq!(changes[0]["value"], "0");
+    assert_eq!(changes[0]["previous"], serde_json::Value::Null);
+    assert_eq!(changes[1]["device"], "AC");
+    assert_eq!(changes[1]["property"], "online");
+    assert_eq!(changes[1]["value"], "1");
+    assert_eq!(changes[1]["previous"], "0");
+}
+
+#[test]
+fn watch_rejects_a_zero_interval() {
+    let fixture = SysfsFixture::laptop();
+
+    for args in [&["watch", "--interval", "0"][..], &["bar", "--continuous", "--interval", "0"]] {
+        let output = fixture.run(args);
+
+        assert!(!output.status.success(), "{:?}", args);
+        assert!(stderr(&output).contains("--interval"), "{}", stderr(&output));
+    }
+}
+
+#[test]
+fn watch_requires_an_existing_device() {
+    let fixture = SysfsFixture::laptop();
+
+    let output = fixture.run(&["watch", "--device", "BAT9"]);
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("No such device"), "{}", stderr(&output));
+}
+
+#[test]
+fn bar_prints_waybar_json_with_the_configured_level() {
+    let fixture = SysfsFixture::laptop();
+    fixture.write_config(concat!(
+        "batteries = [\"BAT0\"]\n",
+        "read_delay_ms = 1000\n",
+        "[[threshold]]\n",
+        "name = \"critical\"\n",
+        "percentage = 90\n",
+    ));
+
+    let output = fixture.run(&["bar"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let module: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(module, serde_json::json!({
+        "text": "87% 4:34",
+        "tooltip": "BAT0: Discharging\n4:34 to empty\n9.12 W",
+        "class": ["critical", "discharging"],
+        "percentage": 87,
+    }));
+}
+
+#[test]
+fn bar_reemits_on_change_with_continuous() {
+    let fixture = SysfsFixture::laptop();
+
+    let bar = KillOnDrop(fixture.command(&["bar", "--bar-format", "polybar", "--continuous", "--interval", "20"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap());
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    fixture.set("BAT0", "capacity", "19");
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    // Nothing the bar shows changed.
+    fixture.set("BAT0", "voltage_now", "12000000");
+    fixture.set("BAT0", "status", "Charging");
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    assert_eq!(stdout(&bar.finish()), "87% 4:34\n%{F#FFB52A}19% 4:34%{F-}\n%{F#FFB52A}19%+ 0:41%{F-}\n");
+}
+
+#[test]
+fn status_skips_batteries_it_cannot_parse() {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT1", "capacity", "-1");
+
+    let output = fixture.run(&["status"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("[WARN] Skipping BAT1: "), "{}", stderr(&output));
+    assert!(stdout(&output).contains("BAT0"), "{}", stdout(&output));
+}
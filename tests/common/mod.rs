@@ -0,0 +1,304 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! A fake `/sys/class/power_supply` tree for driving batteryctl in tests.
+
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Child, Command, Output, Stdio},
+};
+
+use tempfile::TempDir;
+
+pub struct SysfsFixture {
+    dir: TempDir,
+}
+
+impl SysfsFixture {
+    /// Creates an empty power_supply tree.
+    pub fn new() -> Self {
+        let dir = TempDir::new().expect("failed to create the fixture directory");
+        fs::create_dir_all(dir.path().join("power_supply")).unwrap();
+        fs::create_dir_all(dir.path().join("config")).unwrap();
+        fs::create_dir_all(dir.path().join("state")).unwrap();
+
+        Self { dir }
+    }
+
+    /// Creates a tree resembling a dual-battery laptop with an AC adapter and a USB-C port.
+    pub fn laptop() -> Self {
+        let fixture = Self::new();
+
+        fixture.device("BAT0")
+            .attr("type", "Battery")
+            .attr("scope", "System")
+            .attr("present", "1")
+            .attr("status", "Discharging")
+            .attr("capacity", "87")
+            .attr("capacity_level", "Normal")
+            .attr("energy_now", "41760000")
+            .attr("energy_full", "48000000")
+            .attr("energy_full_design", "57020000")
+            .attr("power_now", "9120000")
+            .attr("voltage_now", "12106000")
+            .attr("voltage_min_design", "11520000")
+            .attr("cycle_count", "212")
+            .attr("technology", "Li-poly")
+            .attr("manufacturer", "SMP")
+            .attr("model_name", "5B10W13930")
+            .attr("serial_number", "1234");
+
+        fixture.device("BAT1")
+            .attr("type", "Battery")
+            .attr("scope", "System")
+            .attr("present", "1")
+            .attr("status", "Not charging")
+            .attr("capacity", "64")
+            .attr("capacity_level", "Normal")
+            .attr("charge_now", "1920000")
+            .attr("charge_full", "3000000")
+            .attr("charge_full_design", "3400000")
+            .attr("current_now", "0")
+            .attr("voltage_now", "11870000")
+            .attr("voltage_min_design", "11400000")
+            .attr("cycle_count", "0")
+            .attr("technology", "Li-ion")
+            .attr("manufacturer", "LGC")
+            .attr("model_name", "01AV405")
+            .attr("serial_number", " 5678");
+
+        fixture.device("AC")
+            .attr("type", "Mains")
+            .attr("online", "0");
+
+        fixture.device("ucsi-source-psy-USBC000:001")
+            .attr("type", "USB")
+            .attr("scope", "System")
+            .attr("online", "0")
+            .attr("usb_type", "C [PD] PD_PPS")
+            // ucsi doesn't export its limits in uevent.
+            .file_only("voltage_max", "5000000")
+            .file_only("current_max", "0");
+
+        fixture
+    }
+
+    /// The directory to pass as the sysfs root.
+    pub fn root(&self) -> PathBuf {
+        self.dir.path().join("power_supply")
+    }
+
+    /// The directory to use as `XDG_CONFIG_HOME`, so tests never touch the real config.
+    pub fn config_home(&self) -> PathBuf {
+        self.dir.path().join("config")
+    }
+
+    /// The directory to use as `XDG_STATE_HOME`, where the daemon records its history.
+    pub fn state_home(&self) -> PathBuf {
+        self.dir.path().join("state")
+    }
+
+    /// Creates a device directory, returning a builder for its attributes.
+    pub fn device(&self, name: &str) -> DeviceBuilder {
+        let path = self.root().join(name);
+        fs::create_dir_all(&path).unwrap();
+
+        DeviceBuilder { path }
+    }
+
+    /// The path of a device directory.
+    pub fn device_path(&self, name: &str) -> PathBuf {
+        self.root().join(name)
+    }
+
+    /// Overwrites a single attribute of an existing device, keeping its uevent in sync.
+    pub fn set(&self, device: &str, attr: &str, value: &str) {
+        write_attr(&self.root().join(device), attr, value);
+    }
+
+    /// Makes every attribute of a device read-only except the given ones,
+    /// like the modes sysfs gives them.
+    pub fn writable_only(&self, device: &str, writable: &[&str]) {
+        for entry in fs::read_dir(self.device_path(device)).unwrap().flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let mode = if writable.contains(&name.as_str()) { 0o644 } else { 0o444 };
+            fs::set_permissions(entry.path(), fs::Permissions::from_mode(mode)).unwrap();
+        }
+    }
+
+    /// Runs the batteryctl binary against this tree.
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().expect("failed to run batteryctl")
+    }
+
+    /// Builds a batteryctl command against this tree without running it.
+    pub fn command(&self, args: &[&str]) -> Command {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_batteryctl"));
+        cmd.args(args)
+            .arg("--sysfs-root")
+            .arg(self.root())
+            .env("XDG_CONFIG_HOME", self.config_home())
+            .env("XDG_STATE_HOME", self.state_home())
+            .env_remove("BATTERYCTL_SYSFS_ROOT");
+        cmd
+    }
+}
+
+pub struct DeviceBuilder {
+    path: PathBuf,
+}
+
+impl DeviceBuilder {
+    /// Adds an attribute file and its `POWER_SUPPLY_*` uevent line.
+    pub fn attr(self, name: &str, value: &str) -> Self {
+        write_attr(&self.path, name, value);
+        self
+    }
+
+    /// Adds an attribute file without a uevent line, like drivers with an incomplete uevent.
+    pub fn file_only(self, name: &str, value: &str) -> Self {
+        write_atomic(&self.path.join(name), &format!("{}\n", value));
+        self
+    }
+}
+
+/// Writes an attribute the way the kernel exposes it, newline-terminated,
+/// and mirrors it into the device's uevent file.
+fn write_attr(device: &Path, name: &str, value: &str) {
+    write_atomic(&device.join(name), &format!("{}\n", value));
+
+    let uevent_path = device.join("uevent");
+    let key = format!("POWER_SUPPLY_{}=", name.to_uppercase());
+    let mut lines: Vec<String> = match fs::read_to_string(&uevent_path) {
+        Ok(contents) => contents.lines().map(str::to_string).collect(),
+        Err(_) => vec![format!("POWER_SUPPLY_NAME={}", device.file_name().unwrap().to_string_lossy())],
+    };
+    let line = format!("{}{}", key, value);
+    match lines.iter().position(|l| l.starts_with(&key)) {
+        Some(i) => lines[i] = line,
+        None => lines.push(line),
+    }
+    write_atomic(&uevent_path, &(lines.join("\n") + "\n"));
+}
+
+/// Replaces a file in one step, so a running daemon never reads it half-written.
+fn write_atomic(path: &Path, contents: &str) {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents).unwrap();
+    fs::rename(tmp, path).unwrap();
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+impl SysfsFixture {
+    /// Writes the batteryctl config used by the binary under this fixture.
+    pub fn write_config(&self, contents: &str) {
+        let dir = self.config_home().join("batteryctl");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("config.toml"), contents).unwrap();
+    }
+
+    /// A scratch file path inside the fixture, e.g. for handlers to write to.
+    pub fn scratch(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+}
+
+/// Polls `check` until it returns true or a few seconds pass.
+pub fn wait_until(mut check: impl FnMut() -> bool) -> bool {
+    for _ in 0..100 {
+        if check() {
+            return true;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    false
+}
+
+/// Kills a child process when dropped, so failing tests don't leak daemons.
+pub struct KillOnDrop(pub std::process::Child);
+
+impl KillOnDrop {
+    /// Kills the process and returns what it printed.
+    pub fn finish(mut self) -> Output {
+        let _ = self.0.kill();
+        let mut output = Output {
+            status: self.0.wait().unwrap(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        };
+        if let Some(mut out) = self.0.stdout.take() {
+            std::io::Read::read_to_end(&mut out, &mut output.stdout).unwrap();
+        }
+        if let Some(mut err) = self.0.stderr.take() {
+            std::io::Read::read_to_end(&mut err, &mut output.stderr).unwrap();
+        }
+        output
+    }
+}
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// A private bus, so tests never talk to the desktop's notification server or logind.
+pub struct PrivateBus {
+    daemon: Child,
+    pub address: String,
+}
+
+impl PrivateBus {
+    /// Starts a bus, or returns `None` if `dbus-daemon` isn't installed.
+    pub fn start() -> Option<Self> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address).unwrap();
+
+        Some(Self {
+            daemon,
+            address: address.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for PrivateBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
@@ -0,0 +1,163 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+mod common;
+
+use std::{
+    collections::HashMap,
+    fs,
+    process::Stdio,
+    sync::{Arc, Mutex},
+};
+
+use common::{wait_until, KillOnDrop, PrivateBus, SysfsFixture};
+use zbus::zvariant::OwnedValue;
+
+const HELPER_NAME: &str = "io.github.DucktectiveCZ.Batteryctl1";
+const HELPER_PATH: &str = "/io/github/DucktectiveCZ/Batteryctl1";
+
+/// A polkit authority that records the actions it's asked about and answers
+/// with a fixed decision.
+struct StubPolkit {
+    authorized: bool,
+    checks: Arc<Mutex<Vec<String>>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.PolicyKit1.Authority")]
+impl StubPolkit {
+    fn check_authorization(
+        &self,
+        _subject: (String, HashMap<String, OwnedValue>),
+        action_id: String,
+        details: HashMap<String, String>,
+        flags: u32,
+        _cancellation_id: String,
+    ) -> (bool, bool, HashMap<String, String>) {
+        self.checks.lock().unwrap().push(format!("{} {}={} flags={}", action_id, details["property"], details["value"], flags));
+        (self.authorized, false, HashMap::new())
+    }
+}
+
+fn serve_polkit(bus: &PrivateBus, authorized: bool, checks: &Arc<Mutex<Vec<String>>>) -> zbus::blocking::Connection {
+    zbus::blocking::connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name("org.freedesktop.PolicyKit1")
+        .unwrap()
+        .serve_at("/org/freedesktop/PolicyKit1/Authority", StubPolkit { authorized, checks: checks.clone() })
+        .unwrap()
+        .build()
+        .unwrap()
+}
+
+/// Starts the helper on the bus and waits until it owns its name.
+fn start_helper(fixture: &SysfsFixture, bus: &PrivateBus) -> (KillOnDrop, zbus::blocking::Connection) {
+    let helper = KillOnDrop(fixture.command(&["helper"])
+        .env("DBUS_SYSTEM_BUS_ADDRESS", &bus.address)
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap());
+    let client = zbus::blocking::connection::Builder::address(bus.address.as_str()).unwrap().build().unwrap();
+    let dbus = zbus::blocking::fdo::DBusProxy::new(&client).unwrap();
+    assert!(wait_until(|| dbus.name_has_owner(HELPER_NAME.try_into().unwrap()).unwrap_or(false)));
+
+    (helper, client)
+}
+
+fn set_property(client: &zbus::blocking::Connection, device: &str, name: &str, value: &str) -> zbus::Result<()> {
+    call(client, "SetProperty", device, name, value)
+}
+
+fn call(client: &zbus::blocking::Connection, method: &str, device: &str, name: &str, value: &str) -> zbus::Result<()> {
+    client.call_method(Some(HELPER_NAME), HELPER_PATH, Some(HELPER_NAME), method, &(device, name, value))?;
+    Ok(())
+}
+
+fn error_name(result: zbus::Result<()>) -> String {
+    match result {
+        Err(zbus::Error::MethodError(name, _, _)) => name.to_string(),
+        other => panic!("expected a D-Bus error, got {:?}", other),
+    }
+}
+
+fn charge_fixture() -> SysfsFixture {
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "charge_control_end_threshold", "100");
+    fixture.set("BAT0", "alarm", "0");
+    // Without a bracketed listing there's no telling which values are safe.
+    fixture.set("BAT0", "charge_types", "Fast");
+    fixture.writable_only("BAT0", &["charge_control_end_threshold", "alarm", "charge_types"]);
+    fixture
+}
+
+#[test]
+fn helper_writes_authorized_charge_settings() {
+    let bus = match PrivateBus::start() {
+        Some(val) => val,
+        None => return eprintln!("dbus-daemon isn't installed, skipping"),
+    };
+    let checks = Arc::new(Mutex::new(Vec::new()));
+    let _polkit = serve_polkit(&bus, true, &checks);
+    let fixture = charge_fixture();
+    let (_helper, client) = start_helper(&fixture, &bus);
+
+    set_property(&client, "BAT0", "charge_control_end_threshold", "80").unwrap();
+
+    let path = fixture.device_path("BAT0").join("charge_control_end_threshold");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "80");
+
+    // The daemon's writes must not make polkit ask for a password.
+    call(&client, "SetPropertyNoninteractive", "BAT0", "charge_control_end_threshold", "75").unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "75");
+    assert_eq!(*checks.lock().unwrap(), vec![
+        "io.github.ducktectivecz.batteryctl.set-charge-settings charge_control_end_threshold=80 flags=1".to_string(),
+        "io.github.ducktectivecz.batteryctl.set-charge-settings charge_control_end_threshold=75 flags=0".to_string(),
+    ]);
+}
+
+#[test]
+fn helper_rejects_unauthorized_and_invalid_writes() {
+    let bus = match PrivateBus::start() {
+        Some(val) => val,
+        None => return eprintln!("dbus-daemon isn't installed, skipping"),
+    };
+    let checks = Arc::new(Mutex::new(Vec::new()));
+    let _polkit = serve_polkit(&bus, false, &checks);
+    let fixture = charge_fixture();
+    let (_helper, client) = start_helper(&fixture, &bus);
+
+    let denied = set_property(&client, "BAT0", "charge_control_end_threshold", "80");
+    assert_eq!(error_name(denied), "org.freedesktop.DBus.Error.AccessDenied");
+
+    // Invalid writes are refused before polkit is asked.
+    for (device, name, value) in [
+        ("BAT0", "charge_control_end_threshold", "180"),
+        ("BAT0", "alarm", "1"),
+        ("BAT0", "charge_types", "Bypass"),
+        ("../BAT0", "charge_control_end_threshold", "80"),
+        ("BAT1", "charge_control_end_threshold", "80"),
+    ] {
+        let rejected = set_property(&client, device, name, value);
+        assert_eq!(error_name(rejected), "org.freedesktop.DBus.Error.InvalidArgs", "{} {}={}", device, name, value);
+    }
+
+    assert_eq!(checks.lock().unwrap().len(), 1);
+    let path = fixture.device_path("BAT0").join("charge_control_end_threshold");
+    assert_eq!(fs::read_to_string(path).unwrap(), "100\n");
+}
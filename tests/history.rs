@@ -0,0 +1,160 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+mod common;
+
+use std::{fs, process::Output};
+
+use common::{stderr, stdout, SysfsFixture};
+
+/// A fixture with two batteries discharging over an hour, starting at 2024-05-01 12:00 UTC.
+fn fixture_with_history() -> SysfsFixture {
+    let fixture = SysfsFixture::laptop();
+    let dir = fixture.state_home().join("batteryctl");
+    fs::create_dir_all(&dir).unwrap();
+
+    // The rotated file holds the older samples.
+    fs::write(dir.join("history.1.tsv"), concat!(
+        "# timestamp\tdevice\tcapacity\tstatus\tenergy_uwh\tpower_uw\tvoltage_uv\ttemp_dc\n",
+        "1714564800\tBAT0\t90\tDischarging\t43200000\t9000000\t12200000\t310\n",
+        "1714564800\tBAT1\t64\tNot charging\t22790400\t0\t11870000\t\n",
+        "1714566600\tBAT0\t70\tDischarging\t33600000\t9000000\t12000000\t315\n",
+    )).unwrap();
+    fs::write(dir.join("history.tsv"), concat!(
+        "# timestamp\tdevice\tcapacity\tstatus\tenergy_uwh\tpower_uw\tvoltage_uv\ttemp_dc\n",
+        "1714568400\tBAT0\t50\tDischarging\t24000000\t9000000\t11800000\t320\n",
+        "1714568400\tBAT0\tgarbage\n",
+    )).unwrap();
+
+    fixture
+}
+
+fn history(fixture: &SysfsFixture, args: &[&str]) -> Output {
+    let mut all_args = vec!["history"];
+    all_args.extend(args);
+
+    fixture.command(&all_args).env("TZ", "UTC").output().unwrap()
+}
+
+#[test]
+fn exports_csv_across_rotated_files() {
+    let fixture = fixture_with_history();
+
+    let output = history(&fixture, &["--format", "csv"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), concat!(
+        "timestamp,time,device,capacity,status,energy_uwh,power_uw,voltage_uv,temp_dc\n",
+        "1714564800,2024-05-01T12:00:00+00:00,BAT0,90,Discharging,43200000,9000000,12200000,310\n",
+        "1714566600,2024-05-01T12:30:00+00:00,BAT0,70,Discharging,33600000,9000000,12000000,315\n",
+        "1714568400,2024-05-01T13:00:00+00:00,BAT0,50,Discharging,24000000,9000000,11800000,320\n",
+    ));
+    assert!(stderr(&output).contains("[WARN] Skipping line 3 of "), "{}", stderr(&output));
+}
+
+#[test]
+fn filters_by_time_and_device() {
+    let fixture = fixture_with_history();
+
+    let output = history(&fixture, &["--format", "jsonl", "--all", "--until", "2024-05-01T12:15"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), concat!(
+        r#"{"capacity":90,"device":"BAT0","energy_uwh":43200000,"power_uw":9000000,"status":"Discharging","temp_dc":310,"time":"2024-05-01T12:00:00+00:00","timestamp":1714564800,"voltage_uv":12200000}"#, "\n",
+        r#"{"capacity":64,"device":"BAT1","energy_uwh":22790400,"power_uw":0,"status":"Not charging","temp_dc":null,"time":"2024-05-01T12:00:00+00:00","timestamp":1714564800,"voltage_uv":11870000}"#, "\n",
+    ));
+
+    let output = history(&fixture, &["--format", "csv", "-d", "BAT1", "--since", "1714564801"]);
+    assert_eq!(stdout(&output).lines().count(), 1);
+
+    let output = history(&fixture, &["--since", "last tuesday"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Invalid time 'last tuesday'"), "{}", stderr(&output));
+}
+
+#[test]
+fn draws_capacity_charts() {
+    let fixture = fixture_with_history();
+
+    let output = history(&fixture, &["--format", "sparkline"]);
+    assert_eq!(stdout(&output), "BAT0 2024-05-01T12:00:00+00:00 ▇▆▅ 2024-05-01T13:00:00+00:00\n");
+
+    let output = history(&fixture, &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), concat!(
+        "BAT0: 3 samples from 2024-05-01T12:00:00+00:00 to 2024-05-01T13:00:00+00:00\n",
+        "100% │▂\n",
+        "     │█\n",
+        "     │█▅\n",
+        "     │██\n",
+        "     │███\n",
+        "     │███\n",
+        "     │███\n",
+        "  0% │███\n",
+        "     └───\n",
+    ));
+
+    let output = history(&fixture, &["--since", "2030-01-01"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn lists_sessions_with_energy_statistics() {
+    let fixture = fixture_with_history();
+    fs::write(fixture.state_home().join("batteryctl").join("history.tsv"), concat!(
+        "1714568400\tBAT0\t50\tDischarging\t24000000\t9000000\t11800000\t320\n",
+        "1714570200\tBAT0\t50\tCharging\t24000000\t45000000\t12400000\t330\n",
+        "1714572000\tBAT0\t80\tCharging\t38400000\t15000000\t12600000\t335\n",
+    )).unwrap();
+
+    let mut command = fixture.command(&["sessions", "--all"]);
+    let output = command.env("TZ", "UTC").output().unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), concat!(
+        "DEVICE  KIND       START             DURATION  CAPACITY    ENERGY    AVG_POWER  PEAK_POWER\n",
+        "BAT0    discharge  2024-05-01 12:00  1h 00m    90% -> 50%  19.20 Wh  9.00 W     9.00 W\n",
+        "BAT0    charge     2024-05-01 13:30  0h 30m    50% -> 80%  14.40 Wh  30.00 W    45.00 W\n",
+    ));
+}
+
+#[test]
+fn health_trend_projects_the_replacement_date() {
+    let fixture = SysfsFixture::laptop();
+    let dir = fixture.state_home().join("batteryctl");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("capacity.tsv"), concat!(
+        "# timestamp\tdevice\tfull\tfull_design\tcycle_count\n",
+        "1714564800\tBAT0\t90000000\t100000000\t100\n",
+        "1714564800\tBAT1\t3000000\t3400000\t0\n",
+        "1717156800\tBAT0\t89000000\t100000000\t110\n",
+        "1719748800\tBAT0\t88000000\t100000000\t120\n",
+    )).unwrap();
+
+    let output = fixture.command(&["health", "--trend", "--all"]).env("TZ", "UTC").output().unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains(concat!(
+        "  trend:            -1.01% per month over 60 days (3 records)\n",
+        "  per_100_cycles:   -10.00%\n",
+        "  replacement:      70% of design around 2025-12-22\n",
+    )), "{}", stdout);
+    assert!(stdout.contains("  trend:            not enough data yet, the daemon records the capacity daily\n"), "{}", stdout);
+}
@@ -0,0 +1,144 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+mod common;
+
+use std::{
+    collections::HashMap,
+    process::Stdio,
+    sync::{Arc, Mutex},
+};
+
+use common::{wait_until, KillOnDrop, PrivateBus, SysfsFixture};
+use zbus::zvariant::OwnedValue;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Call {
+    app_name: String,
+    replaces_id: u32,
+    icon: String,
+    summary: String,
+    body: String,
+    urgency: Option<u8>,
+}
+
+/// A notification server that records what it's asked to show.
+struct StubServer {
+    calls: Arc<Mutex<Vec<Call>>>,
+    next_id: u32,
+}
+
+#[zbus::interface(name = "org.freedesktop.Notifications")]
+impl StubServer {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &mut self,
+        app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        summary: String,
+        body: String,
+        _actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        _expire_timeout: i32,
+    ) -> u32 {
+        self.calls.lock().unwrap().push(Call {
+            app_name,
+            replaces_id,
+            icon: app_icon,
+            summary,
+            body,
+            urgency: hints.get("urgency").and_then(|v| u8::try_from(v).ok()),
+        });
+
+        if replaces_id != 0 {
+            return replaces_id;
+        }
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+#[test]
+fn daemon_shows_and_replaces_notifications() {
+    let bus = match PrivateBus::start() {
+        Some(val) => val,
+        None => return eprintln!("dbus-daemon isn't installed, skipping"),
+    };
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let _server = zbus::blocking::connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name("org.freedesktop.Notifications")
+        .unwrap()
+        .serve_at("/org/freedesktop/Notifications", StubServer { calls: calls.clone(), next_id: 0 })
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let fixture = SysfsFixture::laptop();
+    fixture.set("BAT0", "capacity", "30");
+    fixture.write_config(concat!(
+        "batteries = [\"BAT0\"]\n",
+        "read_delay_ms = 20\n",
+        "\n",
+        "[notifications]\n",
+        "body = \"{device} at {capacity}%, {level}\"\n",
+        "\n",
+        "[[threshold]]\n",
+        "name = \"bad\"\n",
+        "percentage = 20\n",
+        "notify = true\n",
+        "\n",
+        "[[threshold]]\n",
+        "name = \"critical\"\n",
+        "percentage = 5\n",
+        "notify = { urgency = \"critical\", summary = \"Plug in {device}!\", icon = \"dialog-warning\" }\n",
+    ));
+
+    let _daemon = KillOnDrop(fixture.command(&["daemon"])
+        .env("DBUS_SESSION_BUS_ADDRESS", &bus.address)
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap());
+    let count = || calls.lock().unwrap().len();
+
+    fixture.set("BAT0", "capacity", "20");
+    assert!(wait_until(|| count() == 1));
+    fixture.set("BAT0", "capacity", "5");
+    assert!(wait_until(|| count() == 2));
+
+    assert_eq!(*calls.lock().unwrap(), vec![
+        Call {
+            app_name: "batteryctl".to_string(),
+            replaces_id: 0,
+            icon: "battery-low".to_string(),
+            summary: "Battery bad".to_string(),
+            body: "BAT0 at 20%, bad".to_string(),
+            urgency: Some(1),
+        },
+        Call {
+            app_name: "batteryctl".to_string(),
+            replaces_id: 1,
+            icon: "dialog-warning".to_string(),
+            summary: "Plug in BAT0!".to_string(),
+            body: "BAT0 at 5%, critical".to_string(),
+            urgency: Some(2),
+        },
+    ]);
+}
@@ -0,0 +1,165 @@
+// MIT License
+// Copyright (c) 2024 DucktectiveCZ
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// provided to do so, subject to the following condition:
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+mod common;
+
+use common::{stdout, SysfsFixture};
+use serde_json::{json, Value};
+
+fn run_json(fixture: &SysfsFixture, args: &[&str]) -> (bool, Value) {
+    let output = fixture.run(&[args, &["--output", "json"]].concat());
+    let value = serde_json::from_str(&stdout(&output)).unwrap_or_else(|e| panic!("{}: {:?}", e, stdout(&output)));
+
+    (output.status.success(), value)
+}
+
+#[test]
+fn lists_devices_as_json() {
+    let fixture = SysfsFixture::laptop();
+
+    let (success, value) = run_json(&fixture, &["list-devices", "--type", "battery"]);
+
+    assert!(success);
+    assert_eq!(value, json!({
+        "devices": [
+            {
+                "name": "BAT0",
+                "type": "Battery",
+                "scope": "System",
+                "online": null,
+                "present": true,
+                "model": "5B10W13930",
+                "capacity": 87,
+                "status": "Discharging",
+            },
+            {
+                "name": "BAT1",
+                "type": "Battery",
+                "scope": "System",
+                "online": null,
+                "present": true,
+                "model": "01AV405",
+                "capacity": 64,
+                "status": "Not charging",
+            },
+        ],
+    }));
+}
+
+#[test]
+fn prints_properties_and_estimates_as_json() {
+    let fixture = SysfsFixture::laptop();
+
+    let (_, value) = run_json(&fixture, &["get-property", "capacity"]);
+    assert_eq!(value, json!({ "device": "BAT0", "property": "capacity", "value": "87" }));
+
+    let (_, value) = run_json(&fixture, &["get-property", "--all", "-d", "AC"]);
+    assert_eq!(value, json!({ "device": "AC", "properties": { "name": "AC", "online": "0", "type": "Mains" } }));
+
+    let (_, value) = run_json(&fixture, &["time-left"]);
+    assert_eq!(value, json!({
+        "device": "BAT0",
+        "status": "Discharging",
+        "time_to_empty_secs": 16484,
+        "time_to_full_secs": null,
+    }));
+}
+
+#[test]
+fn prints_the_status_as_json() {
+    let fixture = SysfsFixture::laptop();
+
+    let (_, value) = run_json(&fixture, &["status"]);
+
+    assert_eq!(value["on_ac"], false);
+    assert_eq!(value["adapters"], json!([
+        { "name": "AC", "type": "Mains", "online": false },
+        { "name": "ucsi-source-psy-USBC000:001", "type": "USB", "online": false },
+    ]));
+    let names: Vec<&str> = value["batteries"].as_array().unwrap().iter().map(|b| b["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["BAT0", "BAT1"]);
+    assert_eq!(value["batteries"][0]["time_to_empty_secs"], 16484);
+    assert_eq!(value["total"]["status"], "Discharging");
+    assert!((value["total"]["capacity_percent"].as_f64().unwrap() - 77.20).abs() < 0.01, "{}", value["total"]);
+}
+
+#[test]
+fn prints_health_as_json() {
+    let fixture = SysfsFixture::laptop();
+
+    let (_, value) = run_json(&fixture, &["health", "-d", "BAT1"]);
+
+    let bat = &value["batteries"][0];
+    assert_eq!(bat["device"], "BAT1");
+    assert_eq!(bat["verdict"], "good");
+    assert_eq!(bat["capacity_unit"], "Ah");
+    assert_eq!(bat["cycle_count"], 0);
+    assert!((bat["health_percent"].as_f64().unwrap() - 88.235).abs() < 0.01, "{}", bat);
+    // Only there with --trend.
+    assert!(bat.get("trend").is_none());
+}
+
+#[test]
+fn prints_config_values_as_json_and_toml() {
+    let fixture = SysfsFixture::laptop();
+    fixture.write_config("batteries = [\"BAT0\"]\nread_delay_ms = 1000\nfull_handler = [\"logger\", \"full\"]\n");
+
+    let (_, value) = run_json(&fixture, &["config-get", "full-handler"]);
+    assert_eq!(value, json!({ "full_handler": ["logger", "full"] }));
+
+    let (_, value) = run_json(&fixture, &["config-get", "ac-connected-handler"]);
+    assert_eq!(value, json!({ "ac_connected_handler": null }));
+
+    let output = fixture.run(&["config-get", "batteries", "--output", "toml"]);
+    assert_eq!(stdout(&output), "batteries = [\"BAT0\"]\n");
+
+    let (_, value) = run_json(&fixture, &["config-set", "read-delay", "5000"]);
+    assert_eq!(value, json!({ "read_delay_ms": 5000 }));
+}
+
+#[test]
+fn reports_errors_in_the_output_format() {
+    let fixture = SysfsFixture::laptop();
+
+    let (success, value) = run_json(&fixture, &["get-property", "nonexistent"]);
+    assert!(!success);
+    assert_eq!(value, json!({ "error": "The device doesn't expose the 'nonexistent' property" }));
+
+    let output = fixture.run(&["config-set", "read-delay", "soon", "--output", "toml"]);
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "error = \"The value has to be a valid unsigned 64-bit integer.\"\n");
+}
+
+#[test]
+fn reports_usage_and_config_errors_in_the_output_format() {
+    let fixture = SysfsFixture::laptop();
+
+    let (success, value) = run_json(&fixture, &["get-property", "--start", "150"]);
+    assert!(!success);
+    assert!(value["error"].as_str().unwrap().contains("--start"), "{}", value);
+
+    let output = fixture.run(&["no-such-operation", "-otoml"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stdout(&output).starts_with("error = "), "{}", stdout(&output));
+
+    fixture.write_config("batteries = \"BAT0\"\n");
+    let (success, value) = run_json(&fixture, &["version"]);
+    assert!(!success);
+    assert!(value["error"].as_str().unwrap().starts_with("Invalid config "), "{}", value);
+}